@@ -0,0 +1,99 @@
+//! Compares the parallel scan with the sequential `WalkDir` loop it replaced,
+//! by running piecut in report mode with different numbers of threads. Scans
+//! the directory given as argument, e.g. `cargo bench -- /mnt/nas`, or a
+//! generated tree otherwise.
+
+use std::{env, fs, io, path, process, thread, time};
+use walkdir::WalkDir;
+
+const RUNS: usize = 3;
+
+/// Creates a tree of 4000 small files in 400 directories.
+fn generate_tree() -> io::Result<path::PathBuf> {
+    let root = env::temp_dir().join(format!("piecut-bench-{}", process::id()));
+    for outer in 0..20 {
+        for inner in 0..20 {
+            let dir = root.join(format!("dir{}", outer)).join(format!("sub{}", inner));
+            fs::create_dir_all(&dir)?;
+            for file in 0..10 {
+                fs::write(dir.join(format!("file{}", file)), vec![0; 100 * (file + 1)])?;
+            }
+        }
+    }
+    Ok(root)
+}
+
+/// Walks the directory like the scan did before it was parallel, reading the
+/// metadata of every entry one after another. Returns the fastest run and the
+/// total size.
+fn walkdir_scan(dir: &path::Path) -> (time::Duration, u64) {
+    let mut fastest = time::Duration::MAX;
+    let mut total_size = 0;
+    for _ in 0..RUNS {
+        let start = time::Instant::now();
+        total_size = WalkDir::new(dir).into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        fastest = fastest.min(start.elapsed());
+    }
+    (fastest, total_size)
+}
+
+/// Runs the scan a few times, returns the fastest run and the report.
+fn scan(dir: &path::Path, threads: usize) -> (time::Duration, Vec<u8>) {
+    let mut fastest = time::Duration::MAX;
+    let mut report = Vec::new();
+    for _ in 0..RUNS {
+        let start = time::Instant::now();
+        let output = process::Command::new(env!("CARGO_BIN_EXE_piecut"))
+            .arg(dir)
+            .args(["--format", "json", "--no-cache", "--threads", &threads.to_string()])
+            .output()
+            .expect("couldn't run piecut");
+        fastest = fastest.min(start.elapsed());
+        report = output.stdout;
+    }
+    (fastest, report)
+}
+
+fn main() -> io::Result<()> {
+    let given = env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let dir = match &given {
+        Some(dir) => path::PathBuf::from(dir),
+        None => generate_tree()?
+    };
+
+    let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut threads = vec![1, 2, 4, cpus];
+    threads.sort_unstable();
+    threads.dedup();
+
+    println!("Scanning {}, fastest of {} runs:", dir.display(), RUNS);
+    let (sequential, total_size) = walkdir_scan(&dir);
+    // the time it takes to start piecut is left out of its scans
+    let empty = env::temp_dir().join(format!("piecut-bench-empty-{}", process::id()));
+    fs::create_dir_all(&empty)?;
+    let (startup, _) = scan(&empty, 1);
+    fs::remove_dir(&empty)?;
+    println!("    WalkDir: {:>8.1} ms, {} bytes", sequential.as_secs_f64() * 1000.0, total_size);
+    // every thread count has to find the same as a single thread
+    let mut expected = None;
+    for &count in &threads {
+        let (elapsed, report) = scan(&dir, count);
+        let elapsed = elapsed.saturating_sub(startup);
+        let check = match &expected {
+            Some(expected) if *expected != report => " (different result!)",
+            _ => ""
+        };
+        expected.get_or_insert(report);
+        println!("{:>3} threads: {:>8.1} ms, {:.2}x{}", count, elapsed.as_secs_f64() * 1000.0,
+            sequential.as_secs_f64() / elapsed.as_secs_f64(), check);
+    }
+
+    if given.is_none() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
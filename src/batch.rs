@@ -0,0 +1,208 @@
+//! Deletes the entries of a list without prompting for every single one,
+//! e.g. the entries of a JSON report saved earlier.
+
+use std::{fs, io, io::BufRead, io::Read, io::Write, path, time, error::Error, collections::HashSet};
+
+use crate::{Settings, json, walk, to_readable_size, delete_file, hard_link_note};
+
+/// A listed entry. Reports also contain the size and modification time at the
+/// time of the scan, which have to match before the entry is deleted.
+struct Listed {
+    path: path::PathBuf,
+    size: Option<u64>,
+    modified: Option<u64>,
+}
+
+/// An entry that still matches the list and can be deleted.
+struct Checked {
+    path: path::PathBuf,
+    size: u64,
+    is_dir: bool,
+    /// Device and inode of hard linked files, their data is only counted once.
+    inode: Option<(u64, u64)>,
+    /// Number of hard links.
+    links: u64,
+}
+
+/// Whether all links of a hard linked file are among `entries`, otherwise
+/// deleting them doesn't free its data.
+fn all_links(entry: &Checked, entries: &[&Checked]) -> bool {
+    entry.inode.is_none_or(|inode| entries.iter().filter(|e| e.inode == Some(inode)).count() as u64 >= entry.links)
+}
+
+/// Adds up the space freed by deleting entries. The data of hard linked
+/// files is counted once, and only if all of their links are deleted.
+fn combined_size(entries: &[&Checked]) -> u64 {
+    let mut linked = HashSet::new();
+    entries.iter()
+        .filter(|e| all_links(e, entries))
+        .filter(|e| e.inode.is_none_or(|inode| linked.insert(inode)))
+        .map(|e| e.size)
+        .sum()
+}
+
+/// Reads the list from a file or from stdin if `source` is "-".
+fn read_list(source: &str) -> Result<Vec<Listed>, Box<dyn Error>> {
+    let mut content = String::new();
+    if source == "-" {
+        io::stdin().read_to_string(&mut content)?;
+    } else {
+        fs::File::open(source)?.read_to_string(&mut content)?;
+    }
+
+    if !content.trim_start().starts_with('{') {
+        // one path per line
+        return Ok(content.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| Listed { path: path::PathBuf::from(line), size: None, modified: None })
+            .collect())
+    }
+
+    let report = json::parse(&content)?;
+    let entries = report.get("entries").and_then(json::Value::as_array)
+        .ok_or("the report doesn't contain any entries")?;
+    let mut list = Vec::<Listed>::new();
+    for entry in entries {
+        let path = entry.get("path").and_then(json::Value::as_str)
+            .ok_or("an entry of the report doesn't contain a path")?;
+        list.push(Listed {
+            path: path::PathBuf::from(path),
+            size: entry.get("apparent_size").or_else(|| entry.get("size")).and_then(json::Value::as_u64),
+            modified: entry.get("modified").and_then(json::Value::as_u64)
+        });
+    }
+    Ok(list)
+}
+
+/// Accumulates the size of everything in a directory, hard links are counted
+/// once. Only used for lists without sizes, see `check`.
+fn dir_size(path: &path::Path) -> u64 {
+    let (walked, _) = walk::walk(path, 1, false, None, (), |_, _, stat, _| {
+        let value = (stat.size, stat.inode.filter(|_| stat.links > 1));
+        Ok(if stat.is_dir { walk::Visit::Dir(value, ()) } else { walk::Visit::File(value) })
+    });
+    let mut linked = HashSet::new();
+    walked.into_iter()
+        .filter_map(|(_, entry)| entry.ok())
+        .filter(|(_, inode)| inode.is_none_or(|inode| linked.insert(inode)))
+        .map(|(size, _)| size)
+        .sum()
+}
+
+/// Checks that a listed entry still exists and wasn't changed since the scan.
+/// The size of a directory depends on the filters of the scan, so directories
+/// are only checked by their modification time.
+fn check(listed: &Listed) -> Result<Checked, String> {
+    let metadata = fs::symlink_metadata(&listed.path).map_err(|_| "no longer exists")?;
+    let is_dir = metadata.is_dir();
+    let stat = walk::Stat::from(&metadata);
+    let size = match (is_dir, listed.size) {
+        (true, Some(size)) => size,
+        (true, None) => dir_size(&listed.path),
+        (false, _) => metadata.len()
+    };
+    if let Some(expected) = listed.size.filter(|_| !is_dir) {
+        if expected != size {
+            return Err(format!("size changed from {} to {}", to_readable_size(expected),
+                to_readable_size(size)))
+        }
+    }
+    if let Some(expected) = listed.modified {
+        let modified = metadata.modified().ok()
+            .and_then(|m| m.duration_since(time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        if modified != Some(expected) {
+            return Err(String::from("modified since the scan"))
+        }
+    }
+    Ok(Checked {
+        path: listed.path.clone(),
+        size,
+        is_dir,
+        inode: stat.inode.filter(|_| !is_dir && stat.links > 1),
+        links: stat.links
+    })
+}
+
+/// Reads the answer to the overall confirmation. If the list was read from
+/// stdin, the answer has to come from the terminal instead.
+fn read_confirmation(from_terminal: bool) -> io::Result<String> {
+    let mut choice = String::new();
+    if from_terminal {
+        let terminal = fs::File::open(if cfg!(windows) { "CONIN$" } else { "/dev/tty" })?;
+        io::BufReader::new(terminal).read_line(&mut choice)?;
+    } else {
+        io::stdin().read_line(&mut choice)?;
+    }
+    Ok(choice)
+}
+
+/// Deletes all listed entries that still match the list after a single
+/// confirmation and prints a summary of the reclaimed space.
+pub fn delete_from(source: &str, settings: &Settings) -> Result<(), Box<dyn Error>> {
+    let list = read_list(source)?;
+    let mut checked = Vec::<Checked>::new();
+    for listed in &list {
+        match check(listed) {
+            Ok(entry) => checked.push(entry),
+            Err(reason) => println!("Skipping {}: {}", listed.path.display(), reason)
+        }
+    }
+    // entries inside listed directories are deleted along with them
+    let dirs: Vec<path::PathBuf> = checked.iter()
+        .filter(|e| e.is_dir)
+        .map(|e| e.path.clone())
+        .collect();
+    checked.retain(|e| !dirs.iter().any(|d| e.path != *d && e.path.starts_with(d)));
+
+    if checked.is_empty() {
+        println!("\nNothing to delete.");
+        return Ok(())
+    }
+    let entries: Vec<&Checked> = checked.iter().collect();
+    println!("\n{} of {} entries ({}) can be deleted:", checked.len(), list.len(),
+        to_readable_size(combined_size(&entries)));
+    for entry in &checked {
+        let suffix = if entry.is_dir { " (dir)" } else { "" };
+        println!("{:>11}  {}{}", to_readable_size(entry.size), entry.path.display(), suffix);
+    }
+    let notes: Vec<String> = checked.iter()
+        .filter(|e| !all_links(e, &entries))
+        .map(|e| hard_link_note(&e.path, e.links))
+        .collect();
+    if !notes.is_empty() {
+        println!("\n{}", notes.join("\n"));
+    }
+
+    let action = match (settings.trash, settings.dry_run) {
+        (true, false) => "Move them to trash",
+        (false, false) => "Delete them",
+        (true, true) => "Dry run: move them to trash",
+        (false, true) => "Dry run: delete them"
+    };
+    print!("\n{}? Type yes to confirm: ", action);
+    io::stdout().flush()?;
+    if read_confirmation(source == "-")?.trim().to_uppercase() != "YES" {
+        println!("\nNothing deleted.");
+        return Ok(())
+    }
+
+    let mut removed = Vec::<&Checked>::new();
+    for entry in &checked {
+        match delete_file(&entry.path, entry.is_dir, settings) {
+            Ok(_) => removed.push(entry),
+            Err(err) => eprintln!("Couldn't delete {}: {}", entry.path.display(), err)
+        }
+    }
+    let (reclaimed, deleted) = (combined_size(&removed), removed.len());
+    let action = if settings.trash { "Moved" } else { "Deleted" };
+    let target = if settings.trash { " to trash" } else { "" };
+    if settings.dry_run {
+        println!("\nDry run: would have {} {} entries{}, reclaiming {}.",
+            action.to_lowercase(), deleted, target, to_readable_size(reclaimed));
+    } else {
+        println!("\n{} {} entries{}, reclaimed {}.", action, deleted, target,
+            to_readable_size(reclaimed));
+    }
+    Ok(())
+}
@@ -0,0 +1,373 @@
+//! Keeps the directory listings of the last scan on disk. Directories whose
+//! modification time didn't change since then aren't read again, their
+//! entries are taken from the cache instead. Changes to the content of files
+//! in those directories aren't noticed, `--no-cache` walks everything again.
+
+use std::{env, fs, io, io::Write, path, time, ffi::OsString, collections::HashMap, collections::HashSet, hash::Hash, hash::Hasher};
+use std::collections::hash_map::DefaultHasher;
+
+use crate::walk::Stat;
+
+/// First line of cache files, changes whenever the format does.
+const HEADER: &str = "piecut-cache 5";
+
+/// A directory listing at the time of the last scan.
+pub struct Dir {
+    pub modified: time::SystemTime,
+    pub entries: Vec<(OsString, Stat)>,
+}
+
+/// Sizes of entries and whether they are directories, by their path relative
+/// to the scanned directory.
+pub type Sizes = HashMap<path::PathBuf, (u64, bool)>;
+
+/// Directory listings by their path relative to the scanned directory.
+pub struct Cache {
+    dirs: HashMap<path::PathBuf, Dir>,
+    /// Filters that changed which entries the scan read, see `Filters::describe_walk`.
+    pub filters: Vec<String>,
+}
+
+impl Cache {
+    pub fn new() -> Cache {
+        Cache { dirs: HashMap::new(), filters: Vec::new() }
+    }
+
+    /// Returns the listing of a directory if it wasn't modified since.
+    pub fn get(&self, relative: &path::Path, modified: Option<time::SystemTime>) -> Option<&Dir> {
+        self.dirs.get(relative).filter(|dir| Some(dir.modified) == modified)
+    }
+
+    pub fn insert(&mut self, relative: path::PathBuf, dir: Dir) {
+        self.dirs.insert(relative, dir);
+    }
+
+    /// Sizes of all entries at the time of the last scan, directories with
+    /// their content. With `disk_usage` the bytes allocated on disk are used.
+    /// Like in the scan, hard links are counted once, for the first link that
+    /// isn't reached through a symlink.
+    pub fn sizes(&self, disk_usage: bool) -> Sizes {
+        let mut walked = Vec::new();
+        self.collect(path::Path::new(""), 0, false, &mut walked);
+        let shared = |stat: &Stat, through_symlink: bool| stat.inode
+            .filter(|_| !stat.is_dir && (stat.links > 1 || through_symlink));
+        let real: HashSet<(u64, u64)> = walked.iter()
+            .filter(|(_, _, _, through_symlink)| !through_symlink)
+            .filter_map(|&(_, _, stat, _)| stat.inode.filter(|_| !stat.is_dir))
+            .collect();
+        let mut linked = HashSet::new();
+        let mut sizes = Sizes::new();
+        // directories leading to the current entry, by depth
+        let mut parents = Vec::<path::PathBuf>::new();
+        for (path, depth, stat, through_symlink) in walked {
+            parents.truncate(depth);
+            let counted = match shared(stat, through_symlink) {
+                Some(inode) if through_symlink => !real.contains(&inode) && linked.insert(inode),
+                Some(inode) => linked.insert(inode),
+                None => true
+            };
+            let size = match (counted, disk_usage) {
+                (false, _) => 0,
+                (true, true) => stat.allocated,
+                (true, false) => stat.size
+            };
+            for parent in &parents {
+                sizes.get_mut(parent).unwrap().0 += size;
+            }
+            sizes.insert(path.clone(), (size, stat.is_dir));
+            if stat.is_dir {
+                parents.push(path);
+            }
+        }
+        sizes
+    }
+
+    /// Lists the entries below `relative` in the order a sequential walk finds
+    /// them, with their depth and whether they are reached through a symlink.
+    fn collect<'a>(&'a self, relative: &path::Path, depth: usize, behind_symlink: bool,
+            walked: &mut Vec<(path::PathBuf, usize, &'a Stat, bool)>) {
+        let dir = match self.dirs.get(relative) {
+            Some(dir) => dir,
+            None => return
+        };
+        for (name, stat) in &dir.entries {
+            let path = relative.join(name);
+            let through_symlink = behind_symlink || stat.is_symlink;
+            walked.push((path.clone(), depth, stat, through_symlink));
+            if stat.is_dir {
+                self.collect(&path, depth + 1, through_symlink, walked);
+            }
+        }
+    }
+}
+
+/// Directory the caches of all scanned directories are kept in.
+fn cache_dir() -> Option<path::PathBuf> {
+    if let Some(cache_home) = env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        return Some(path::PathBuf::from(cache_home).join("piecut"))
+    }
+    if let Some(local) = env::var_os("LOCALAPPDATA").filter(|_| cfg!(windows)) {
+        return Some(path::PathBuf::from(local).join("piecut"))
+    }
+    env::var_os("HOME").map(|home| path::PathBuf::from(home).join(".cache/piecut"))
+}
+
+/// Location of the cache of a scanned directory, named after a hash of its path.
+fn cache_file(root: &path::Path) -> Option<path::PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+    cache_dir().map(|dir| dir.join(format!("{:016x}", hasher.finish())))
+}
+
+#[cfg(unix)]
+fn to_bytes(name: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    name.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn to_bytes(name: &std::ffi::OsStr) -> Vec<u8> {
+    name.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Percent-encodes a path like the path component of a URL, so it can't
+/// contain spaces or line breaks.
+pub fn encode(name: &std::ffi::OsStr) -> String {
+    let mut encoded = String::new();
+    for byte in to_bytes(name) {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+                    | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte))
+        }
+    }
+    encoded
+}
+
+fn decode(encoded: &str) -> Option<OsString> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = encoded.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(from_bytes(decoded))
+}
+
+fn encode_inode(inode: Option<(u64, u64)>) -> String {
+    match inode {
+        Some((dev, ino)) => format!("{}:{}", dev, ino),
+        None => String::from("-")
+    }
+}
+
+fn decode_inode(encoded: &str) -> Option<Option<(u64, u64)>> {
+    if encoded == "-" {
+        return Some(None)
+    }
+    let (dev, ino) = encoded.split_once(':')?;
+    Some(Some((dev.parse().ok()?, ino.parse().ok()?)))
+}
+
+fn encode_time(time: Option<time::SystemTime>) -> String {
+    match time.and_then(|t| t.duration_since(time::UNIX_EPOCH).ok()) {
+        Some(since) => since.as_nanos().to_string(),
+        None => String::from("-")
+    }
+}
+
+fn decode_time(encoded: &str) -> Option<Option<time::SystemTime>> {
+    if encoded == "-" {
+        return Some(None)
+    }
+    let nanos = encoded.parse::<u128>().ok()?;
+    let since = time::Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32);
+    Some(Some(time::UNIX_EPOCH + since))
+}
+
+/// Reads the cache of a scanned directory. Returns `None` if there is none
+/// or it can't be used.
+pub fn load(root: &path::Path) -> Option<Cache> {
+    let root = fs::canonicalize(root).ok()?;
+    let content = fs::read_to_string(cache_file(&root)?).ok()?;
+    let mut lines = content.lines();
+    if lines.next() != Some(HEADER) || lines.next() != Some(&format!("root {}", encode(root.as_os_str()))) {
+        return None
+    }
+
+    let mut cache = Cache::new();
+    let filters = lines.next()?.strip_prefix("filters")?;
+    for filter in filters.split(' ').filter(|f| !f.is_empty()) {
+        cache.filters.push(decode(filter)?.into_string().ok()?);
+    }
+    let mut current: Option<(path::PathBuf, Dir)> = None;
+    for line in lines {
+        let fields: Vec<&str> = line.splitn(9, ' ').collect();
+        match fields.as_slice() {
+            ["dir", modified, relative] => {
+                if let Some((relative, dir)) = current.take() {
+                    cache.insert(relative, dir);
+                }
+                let dir = Dir { modified: decode_time(modified)??, entries: Vec::new() };
+                current = Some((path::PathBuf::from(decode(relative)?), dir));
+            },
+            [kind, size, allocated, inode, links, modified, created, accessed, name] => {
+                let stat = Stat {
+                    size: size.parse().ok()?,
+                    allocated: allocated.parse().ok()?,
+                    inode: decode_inode(inode)?,
+                    links: links.parse().ok()?,
+                    is_dir: *kind == "d",
+                    is_file: *kind == "f",
+                    is_symlink: *kind == "l",
+                    modified: decode_time(modified)?,
+                    created: decode_time(created)?,
+                    accessed: decode_time(accessed)?
+                };
+                current.as_mut()?.1.entries.push((decode(name)?, stat));
+            },
+            _ => return None
+        }
+    }
+    if let Some((relative, dir)) = current {
+        cache.insert(relative, dir);
+    }
+    Some(cache)
+}
+
+/// Replaces the cache of a scanned directory, which was scanned with `filters`.
+pub fn save(root: &path::Path, cache: &Cache, filters: &[String]) -> io::Result<()> {
+    let root = fs::canonicalize(root)?;
+    let file = cache_file(&root)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    fs::create_dir_all(file.parent().unwrap())?;
+
+    // replace the old cache at once, so it's never read half written
+    let temporary = file.with_extension("tmp");
+    let mut out = io::BufWriter::new(fs::File::create(&temporary)?);
+    writeln!(out, "{}\nroot {}", HEADER, encode(root.as_os_str()))?;
+    let filters: Vec<String> = filters.iter().map(|f| encode(f.as_ref())).collect();
+    writeln!(out, "filters {}", filters.join(" "))?;
+    for (relative, dir) in &cache.dirs {
+        writeln!(out, "dir {} {}", encode_time(Some(dir.modified)), encode(relative.as_os_str()))?;
+        for (name, stat) in &dir.entries {
+            // symlinks are read again anyway, whether they are followed or not
+            let kind = match (stat.is_dir, stat.is_file) {
+                _ if stat.is_symlink => "l",
+                (true, _) => "d",
+                (_, true) => "f",
+                _ => "o"
+            };
+            writeln!(out, "{} {} {} {} {} {} {} {} {}", kind, stat.size, stat.allocated,
+                encode_inode(stat.inode), stat.links, encode_time(stat.modified), encode_time(stat.created), encode_time(stat.accessed),
+                encode(name))?;
+        }
+    }
+    out.flush()?;
+    drop(out);
+    fs::rename(temporary, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn encode_names() {
+        assert_eq!(encode(OsStr::new("src/main.rs")), "src/main.rs");
+        assert_eq!(encode(OsStr::new("a b\nc%")), "a%20b%0Ac%25");
+        assert_eq!(encode(OsStr::new("ä")), "%C3%A4");
+    }
+
+    #[test]
+    fn decode_names() {
+        for name in ["", "plain", "with space", "line\nbreak", "100%", "ümlaut", "~/-_."] {
+            assert_eq!(decode(&encode(OsStr::new(name))).unwrap(), name);
+        }
+        assert_eq!(decode("%41%62").unwrap(), "Ab");
+        assert!(decode("%4").is_none());
+        assert!(decode("%zz").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"bad\xff");
+        assert_eq!(encode(name), "bad%FF");
+        assert_eq!(decode("bad%FF").unwrap(), name);
+    }
+
+    #[test]
+    fn inodes() {
+        assert_eq!(decode_inode(&encode_inode(Some((2049, 131)))), Some(Some((2049, 131))));
+        assert_eq!(decode_inode(&encode_inode(None)), Some(None));
+        assert_eq!(decode_inode("2049"), None);
+    }
+
+    fn stat(size: u64, inode: (u64, u64), links: u64, is_dir: bool) -> Stat {
+        Stat {
+            size,
+            allocated: size.div_ceil(4096) * 4096,
+            inode: Some(inode),
+            links,
+            is_dir,
+            is_file: !is_dir,
+            is_symlink: false,
+            modified: None,
+            created: None,
+            accessed: None
+        }
+    }
+
+    #[test]
+    fn hard_linked_sizes() {
+        let mut cache = Cache::new();
+        let mut insert = |relative: &str, entries: Vec<(&str, Stat)>| cache.insert(relative.into(), Dir {
+            modified: time::UNIX_EPOCH,
+            entries: entries.into_iter().map(|(name, stat)| (name.into(), stat)).collect()
+        });
+        insert("", vec![("a", stat(10, (1, 2), 3, true)), ("c", stat(10, (1, 3), 2, true))]);
+        insert("a", vec![("big", stat(1000, (1, 4), 2, false)), ("b", stat(10, (1, 5), 2, true))]);
+        insert("a/b", vec![("new", stat(500, (1, 6), 1, false))]);
+        insert("c", vec![("biglink", stat(1000, (1, 4), 2, false))]);
+
+        let sizes = cache.sizes(false);
+        let size = |path: &str| sizes[path::Path::new(path)];
+        assert_eq!(size("a"), (1520, true));
+        assert_eq!(size("a/big"), (1000, false));
+        assert_eq!(size("a/b"), (510, true));
+        assert_eq!(size("a/b/new"), (500, false));
+        assert_eq!(size("c"), (10, true));
+        assert_eq!(size("c/biglink"), (0, false));
+        assert_eq!(cache.sizes(true)[path::Path::new("a")], (4 * 4096, true));
+    }
+
+    #[test]
+    fn times() {
+        let time = time::UNIX_EPOCH + time::Duration::new(1_700_000_000, 123_456_789);
+        assert_eq!(decode_time(&encode_time(Some(time))), Some(Some(time)));
+        assert_eq!(decode_time(&encode_time(None)), Some(None));
+        assert_eq!(decode_time("soon"), None);
+    }
+}
@@ -0,0 +1,180 @@
+//! Compares the current scan with an earlier one, either a JSON report or
+//! the cache of the last scan, to show what grew since then.
+
+use std::{fs, path, cmp, collections::HashMap, collections::HashSet, error::Error};
+
+use crate::{Tree, LameFile, cache, json, to_readable_size};
+
+/// Sizes of entries at the time of the earlier scan and the entries of the
+/// current one, both by their path relative to the scanned directory.
+pub struct Diff {
+    root: path::PathBuf,
+    previous: cache::Sizes,
+    current: HashMap<path::PathBuf, usize>,
+    /// Entries of the earlier scan that don't exist anymore. Entries that
+    /// were only filtered out of the current scan aren't among them.
+    gone: HashSet<path::PathBuf>,
+}
+
+/// Reads the entries of a JSON report. Their paths are made relative to the
+/// directory the report was written for. Directories the report doesn't list
+/// get the size of the files listed below them. Without `disk_usage`, the
+/// apparent size is used if the report was written with `--disk-usage`. Hard
+/// links whose data is counted for another link have a size of 0, like in
+/// the scan.
+fn read_report(report: &str, disk_usage: bool) -> Result<cache::Sizes, Box<dyn Error>> {
+    let report = json::parse(&fs::read_to_string(report)?)?;
+    let root = path::PathBuf::from(report.get("root").and_then(json::Value::as_str).unwrap_or(""));
+    let entries = report.get("entries").and_then(json::Value::as_array)
+        .ok_or("the report doesn't contain any entries")?;
+    let mut previous = cache::Sizes::new();
+    let mut dirs = HashMap::<path::PathBuf, u64>::new();
+    for entry in entries {
+        let path = entry.get("path").and_then(json::Value::as_str)
+            .ok_or("an entry of the report doesn't contain a path")?;
+        let apparent_size = entry.get("apparent_size").filter(|_| !disk_usage);
+        let size = apparent_size.or_else(|| entry.get("size")).and_then(json::Value::as_u64)
+            .ok_or("an entry of the report doesn't contain a size")?;
+        let is_dir = entry.get("type").and_then(json::Value::as_str) == Some("dir");
+        let counted = entry.get("counted").and_then(json::Value::as_bool).unwrap_or(true);
+        let size = if counted { size } else { 0 };
+        let path = path::Path::new(path);
+        let relative = path.strip_prefix(&root).unwrap_or(path);
+        if !is_dir {
+            for dir in relative.ancestors().skip(1).filter(|d| d.components().next().is_some()) {
+                *dirs.entry(dir.to_path_buf()).or_insert(0) += size;
+            }
+        }
+        previous.insert(relative.to_path_buf(), (size, is_dir));
+    }
+    for (dir, size) in dirs {
+        previous.entry(dir).or_insert((size, true));
+    }
+    Ok(previous)
+}
+
+/// Reads the earlier scan to compare with, a JSON report or the cache of the
+/// last scan of `root` if no report is given. Has to be called before the
+/// current scan replaces the cache. Entries skipped by the last scan would
+/// look new, so it has to be made with the same `filters`, see
+/// `Filters::describe_walk`.
+pub fn read_snapshot(report: Option<&str>, root: &str, disk_usage: bool, filters: &[String])
+        -> Result<cache::Sizes, Box<dyn Error>> {
+    if let Some(report) = report {
+        return read_report(report, disk_usage)
+    }
+    let cache = cache::load(path::Path::new(root))
+        .ok_or_else(|| format!("There is no earlier scan of {} to compare with", root))?;
+    if cache.filters != filters {
+        let describe = |filters: &[String]| match filters {
+            [] => String::from("none"),
+            _ => filters.join(", ")
+        };
+        return Err(format!("The last scan of {} was made with other filters ({}) than this one ({}). \
+            Use the same filters to compare with it.", root, describe(&cache.filters), describe(filters)).into())
+    }
+    Ok(cache.sizes(disk_usage))
+}
+
+impl Diff {
+    pub fn new(previous: cache::Sizes, tree: &Tree, root: &str) -> Diff {
+        let root = path::PathBuf::from(root);
+        let current: HashMap<_, _> = tree.entries.iter().enumerate()
+            .map(|(i, e)| (e.path.strip_prefix(&root).unwrap_or(&e.path).to_path_buf(), i))
+            .collect();
+        let gone = previous.keys()
+            .filter(|relative| !current.contains_key(*relative))
+            .filter(|relative| fs::symlink_metadata(root.join(relative)).is_err())
+            .cloned()
+            .collect();
+        Diff { root, previous, current, gone }
+    }
+
+    fn previous_size(&self, file: &LameFile) -> Option<u64> {
+        let relative = file.path.strip_prefix(&self.root).unwrap_or(&file.path);
+        self.previous.get(relative).map(|&(size, _)| size)
+    }
+
+    /// How much an entry grew, new entries grew by their whole size.
+    pub fn growth(&self, file: &LameFile) -> u64 {
+        file.size.saturating_sub(self.previous_size(file).unwrap_or(0))
+    }
+
+    /// Describes the change of an entry for the legend, e.g. "grown +1.00 GiB".
+    pub fn describe(&self, file: &LameFile) -> String {
+        let kind = if self.previous_size(file).is_some() { "grown" } else { "new" };
+        format!("{:<5} {:>12}", kind, format!("+{}", to_readable_size(self.growth(file))))
+    }
+
+    /// Lists the entries below `dir` like `Tree::list`, but only those that
+    /// grew, the most on top.
+    pub fn list(&self, tree: &Tree, dir: usize, depth: Option<usize>) -> Vec<usize> {
+        let mut list = tree.list(dir, depth);
+        list.retain(|&i| self.growth(&tree.entries[i]) > 0);
+        list.sort_by_key(|&i| cmp::Reverse(self.growth(&tree.entries[i])));
+        list
+    }
+
+    /// Counts the entries of the earlier scan below `dir` that don't exist
+    /// anymore, at the same level `Tree::list` lists entries at. Returns
+    /// their number and size.
+    pub fn deleted(&self, tree: &Tree, dir: usize, depth: Option<usize>) -> (usize, u64) {
+        let path = &tree.entries[dir].path;
+        let dir = path.strip_prefix(&self.root).unwrap_or(path);
+        let dir_level = dir.components().count();
+        self.previous.iter()
+            .filter(|(relative, &(_, is_dir))| {
+                let level = relative.components().count().saturating_sub(dir_level);
+                let listed = match depth {
+                    None => !is_dir,
+                    Some(depth) => if is_dir { level == depth } else { level <= depth }
+                };
+                listed && level > 0 && relative.starts_with(dir)
+                    && match self.current.get(*relative) {
+                        Some(&i) => tree.entries[i].deleted,
+                        None => self.gone.contains(*relative)
+                    }
+            })
+            .fold((0, 0), |(count, total), (_, &(size, _))| (count + 1, total + size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn growth_and_deleted() {
+        let mut tree = Tree::new();
+        let root = tree.push(None, "root", 0, true);
+        let a = tree.push(Some(root), "a", 0, true);
+        let x = tree.push(Some(a), "x", 100, false);
+        let y = tree.push(Some(a), "y", 50, false);
+        let b = tree.push(Some(root), "b", 0, true);
+        let z = tree.push(Some(b), "z", 10, false);
+        let previous: cache::Sizes = [
+            ("a", (90, true)), ("a/x", (60, false)), ("a/old", (30, false)),
+            ("b", (10, true)), ("b/z", (10, false)),
+            ("c", (5, true)), ("c/w", (5, false))
+        ].iter().map(|&(path, size)| (path::PathBuf::from(path), size)).collect();
+        let diff = Diff::new(previous, &tree, "root");
+
+        assert_eq!(diff.growth(&tree.entries[x]), 40);
+        assert_eq!(diff.growth(&tree.entries[y]), 50);
+        assert_eq!(diff.growth(&tree.entries[z]), 0);
+        assert_eq!(diff.growth(&tree.entries[a]), 60);
+        assert!(diff.describe(&tree.entries[x]).starts_with("grown"));
+        assert!(diff.describe(&tree.entries[y]).starts_with("new"));
+        assert_eq!(diff.list(&tree, root, None), [y, x]);
+        assert_eq!(diff.list(&tree, root, Some(1)), [a]);
+
+        assert_eq!(diff.deleted(&tree, root, None), (2, 35));
+        assert_eq!(diff.deleted(&tree, a, None), (1, 30));
+        assert_eq!(diff.deleted(&tree, root, Some(1)), (1, 5));
+        // entries deleted in this session count as well
+        tree.remove(z);
+        assert_eq!(diff.deleted(&tree, root, None), (3, 45));
+        tree.remove(b);
+        assert_eq!(diff.deleted(&tree, root, Some(1)), (2, 15));
+    }
+}
@@ -0,0 +1,382 @@
+//! Finds files with identical content and lets the user delete the copies or
+//! replace them with hard links.
+
+use std::{fs, io, io::BufRead, io::Read, path, error::Error};
+use std::collections::{HashMap, hash_map::RandomState};
+use std::hash::{BuildHasher, Hasher};
+use piechart::{Color, Data};
+
+use crate::{Tree, Settings, Confirmation, to_readable_size, delete_file, draw_chart, slice_color};
+use crate::tui::{Key, Terminal, POLL};
+
+/// Number of bytes hashed before the full content is compared.
+const HEAD_SIZE: u64 = 4096;
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Files with identical content.
+struct Group {
+    /// Size of a single file.
+    size: u64,
+    /// Tree entries of the files.
+    files: Vec<usize>,
+}
+
+impl Group {
+    /// Space that is freed if all but one file are removed.
+    fn reclaimable(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+}
+
+/// Hashes the content of a file, optionally only the first `limit` bytes.
+fn hash_file(path: &path::Path, limit: Option<u64>, state: &RandomState) -> io::Result<u64> {
+    let file = fs::File::open(path)?;
+    let mut reader = file.take(limit.unwrap_or(u64::MAX));
+    let mut hasher = state.build_hasher();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break
+        }
+        hasher.write(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Splits the files into groups with the same hash. Files that can't be read
+/// and groups with a single file are dropped.
+fn split_by_hash(tree: &Tree, files: Vec<usize>, limit: Option<u64>, state: &RandomState)
+        -> Vec<Vec<usize>> {
+    let mut by_hash = HashMap::<u64, Vec<usize>>::new();
+    for file in files {
+        match hash_file(&tree.entries[file].path, limit, state) {
+            Ok(hash) => by_hash.entry(hash).or_default().push(file),
+            Err(error) => println!("{}: {}. Skipping...", tree.entries[file].path.display(), error)
+        }
+    }
+    by_hash.into_values().filter(|files| files.len() > 1).collect()
+}
+
+/// Whether two files have the same content, compared byte by byte.
+fn same_content(a: &path::Path, b: &path::Path) -> io::Result<bool> {
+    let mut a = io::BufReader::with_capacity(BUFFER_SIZE, fs::File::open(a)?);
+    let mut b = io::BufReader::with_capacity(BUFFER_SIZE, fs::File::open(b)?);
+    loop {
+        let left = a.fill_buf()?;
+        let right = b.fill_buf()?;
+        if left.is_empty() || right.is_empty() {
+            return Ok(left.is_empty() && right.is_empty())
+        }
+        let length = left.len().min(right.len());
+        if left[..length] != right[..length] {
+            return Ok(false)
+        }
+        a.consume(length);
+        b.consume(length);
+    }
+}
+
+/// Splits files with the same hash into groups whose content is actually the
+/// same, so nothing is deleted because of a hash collision. Files that can't
+/// be read and groups with a single file are dropped.
+fn split_by_content(tree: &Tree, files: Vec<usize>) -> Vec<Vec<usize>> {
+    let mut groups = Vec::<Vec<usize>>::new();
+    'files: for file in files {
+        let path = &tree.entries[file].path;
+        for group in &mut groups {
+            match same_content(&tree.entries[group[0]].path, path) {
+                Ok(true) => {
+                    group.push(file);
+                    continue 'files
+                },
+                Ok(false) => {},
+                Err(error) => {
+                    println!("{}: {}. Skipping...", path.display(), error);
+                    continue 'files
+                }
+            }
+        }
+        groups.push(vec![file]);
+    }
+    groups.into_iter().filter(|files| files.len() > 1).collect()
+}
+
+/// Removes files that are hard links to a file that is already in the group,
+/// as deleting them wouldn't free any space.
+#[cfg(unix)]
+fn remove_hard_links(tree: &Tree, files: &mut Vec<usize>) {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut inodes = Vec::<(u64, u64)>::new();
+    files.retain(|&file| match fs::metadata(&tree.entries[file].path) {
+        Ok(metadata) if inodes.contains(&(metadata.dev(), metadata.ino())) => false,
+        Ok(metadata) => {
+            inodes.push((metadata.dev(), metadata.ino()));
+            true
+        },
+        Err(_) => false
+    });
+}
+
+#[cfg(not(unix))]
+fn remove_hard_links(_tree: &Tree, _files: &mut Vec<usize>) {}
+
+/// Groups all files in the tree by size, then by a hash of their first bytes
+/// and a hash of their full content, and finally compares them byte by byte.
+/// Empty files and symlinks are ignored. Sorted by reclaimable space, highest
+/// on top.
+fn find_duplicates(tree: &Tree) -> Vec<Group> {
+    let mut by_size = HashMap::<u64, Vec<usize>>::new();
+    for (i, entry) in tree.entries.iter().enumerate() {
+        // files with the same content have the same length, whatever they take on disk
+        let length = entry.apparent_size.unwrap_or(entry.size);
+        if !entry.is_dir && !entry.is_symlink && !entry.deleted && length > 0 && entry.parent.is_some() {
+            by_size.entry(length).or_default().push(i);
+        }
+    }
+
+    let state = RandomState::new();
+    let mut groups = Vec::<Group>::new();
+    for (size, files) in by_size.into_iter().filter(|(_, files)| files.len() > 1) {
+        for files in split_by_hash(tree, files, Some(HEAD_SIZE), &state) {
+            let files = match size > HEAD_SIZE {
+                true => split_by_hash(tree, files, None, &state),
+                false => vec![files]
+            };
+            for mut files in files {
+                remove_hard_links(tree, &mut files);
+                for files in split_by_content(tree, files) {
+                    groups.push(Group { size, files });
+                }
+            }
+        }
+    }
+
+    groups.sort_by_key(|g| std::cmp::Reverse(g.reclaimable()));
+    groups
+}
+
+/// Replaces `copy` with a hard link to `original`. The link is created next to
+/// the copy first and then renamed, so the copy is never lost on failure.
+fn replace_with_hard_link(original: &path::Path, copy: &path::Path) -> io::Result<()> {
+    let mut temp = copy.as_os_str().to_owned();
+    temp.push(".piecut-link");
+    let temp = path::PathBuf::from(temp);
+    fs::hard_link(original, &temp)?;
+    fs::rename(&temp, copy).inspect_err(|_| {
+        fs::remove_file(&temp).ok();
+    })
+}
+
+/// Creates Piechart data for the duplicate groups on the current page.
+fn create_duplicates_data(tree: &Tree, groups: &[Group], skip: usize, slices: usize) -> Vec<Data> {
+    let total_size: u64 = groups.iter().map(Group::reclaimable).sum();
+    let mut data_size: u64 = 0;
+    let mut data = Vec::<Data>::new();
+
+    for (i, group) in groups.iter().skip(skip).take(slices).enumerate() {
+        let file = &tree.entries[group.files[0]];
+        data.push(Data {
+            label: format!("({}) {:>11} -- {:?} ({} copies)", i + 1,
+                to_readable_size(group.reclaimable()), file.path.file_name().unwrap(),
+                group.files.len()),
+            value: group.reclaimable() as f32 / total_size as f32,
+            color: Some(slice_color(i)),
+            fill: '•'
+        });
+        data_size += group.reclaimable();
+    }
+    // show the space of all other groups as one datapoint
+    let other_size = total_size - data_size;
+    data.push(Data {
+        label: format!("Other: {}", to_readable_size(other_size)),
+        value: other_size as f32 / total_size as f32,
+        color: Some(Color::RGB(100, 100, 100)),
+        fill: '-'
+    });
+
+    data
+}
+
+/// Draws the duplicate groups of the current page and `message` below them,
+/// followed by `prompt` or the help line.
+fn draw_groups(tree: &Tree, groups: &[Group], skip: usize, slices: usize, message: &[String],
+        prompt: Option<&str>) -> io::Result<()> {
+    let reclaimable: u64 = groups.iter().map(Group::reclaimable).sum();
+    let mut screen = format!("{} can be reclaimed from {} groups of duplicates, page {} of {}.\n\n",
+        to_readable_size(reclaimable), groups.len(), skip / slices + 1, groups.len().div_ceil(slices));
+    let mut chart = Vec::new();
+    draw_chart(&mut chart, &create_duplicates_data(tree, groups, skip, slices))?;
+    screen.push_str(&String::from_utf8_lossy(&chart));
+    screen.push('\n');
+    for line in message {
+        screen.push_str(&format!("{}\n", line));
+    }
+    Terminal::draw(&screen, prompt, "1-9 choose copies to delete  n/p next/previous page  q quit")
+}
+
+/// Lists the files of a group and asks which copies should be deleted or
+/// replaced with hard links. At least one copy is always kept. Returns what
+/// happened to be shown below the chart.
+fn process_group(terminal: &mut Terminal, tree: &mut Tree, groups: &mut [Group], index: usize,
+        skip: usize, slices: usize, settings: &Settings) -> io::Result<Vec<String>> {
+    let group = &groups[index];
+    let mut listing = vec![format!("Copies of {:?} ({} each):",
+        tree.entries[group.files[0]].path.file_name().unwrap(), to_readable_size(group.size))];
+    for (i, &file) in group.files.iter().enumerate() {
+        listing.push(format!("  ({}) {}", i + 1, tree.entries[file].path.display()));
+    }
+    let draw = |prompt: &str| draw_groups(tree, groups, skip, slices, &listing, Some(prompt));
+    let choice = terminal.read_line(draw, "Copies to delete, prefix them with l to replace them \
+        with hard links instead:", String::new())?.unwrap_or_default().trim().to_uppercase();
+    let (link, numbers) = match choice.strip_prefix('L') {
+        Some(numbers) => (true, numbers),
+        None => (false, choice.as_str())
+    };
+
+    let mut selected = Vec::<usize>::new();
+    for number in numbers.split_whitespace() {
+        match number.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= group.files.len() => {
+                if !selected.contains(&(n - 1)) {
+                    selected.push(n - 1);
+                }
+            },
+            _ => return Ok(vec![format!("{} is not one of the copies", number)])
+        }
+    }
+    if selected.is_empty() {
+        return Ok(Vec::new())
+    }
+    let keep = match (0..group.files.len()).find(|i| !selected.contains(i)) {
+        Some(keep) => group.files[keep],
+        None => return Ok(vec![String::from("At least one copy has to be kept")])
+    };
+
+    let keep_path = tree.entries[keep].path.clone();
+    let question = match link {
+        true => format!("Replace {} copies with hard links to {}? y/N:", selected.len(), keep_path.display()),
+        false => format!("Delete {} copies ({})? y/N:", selected.len(),
+            to_readable_size(group.size * selected.len() as u64))
+    };
+    let confirmation = Confirmation { notes: Vec::new(), question, type_yes: false };
+    let draw = |prompt: &str| draw_groups(tree, groups, skip, slices, &listing, Some(prompt));
+    if !terminal.confirm(draw, &confirmation)? {
+        return Ok(Vec::new())
+    }
+
+    let group = &mut groups[index];
+    let mut message = Vec::new();
+    let mut reclaimed: u64 = 0;
+    let mut done = Vec::<usize>::new();
+    for &i in &selected {
+        let index = group.files[i];
+        let file = &tree.entries[index];
+        let result = match link {
+            true if settings.dry_run => Ok(()),
+            true => replace_with_hard_link(&keep_path, &file.path),
+            false => delete_file(&file.path, false, settings).map(|_| ())
+        };
+        match result {
+            Ok(()) => {
+                if !link {
+                    tree.remove(index);
+                }
+                reclaimed += group.size;
+                done.push(index);
+            },
+            Err(err) => message.push(format!("Couldn't process {}: {}", file.path.display(), err))
+        }
+    }
+    group.files.retain(|f| !done.contains(f));
+    if settings.dry_run {
+        message.push(format!("Would have reclaimed {} (dry run)", to_readable_size(reclaimed)));
+    } else {
+        message.push(format!("Reclaimed {}", to_readable_size(reclaimed)));
+    }
+    Ok(message)
+}
+
+/// Shows the duplicate groups as pie slices until the user quits.
+pub fn run(tree: &mut Tree, settings: &Settings) -> Result<(), Box<dyn Error>> {
+    println!("Searching for duplicates ...\n");
+    let mut groups = find_duplicates(tree);
+    let mut skip: usize = 0;
+    let mut terminal = Terminal::enter()?;
+    let mut message = Vec::<String>::new();
+    let mut size = None;
+
+    loop {
+        if groups.is_empty() {
+            drop(terminal);
+            println!("No duplicates left, quitting.\n");
+            return Ok(())
+        }
+        let slices = settings.slices();
+        if size != Some(Terminal::size()) {
+            size = Some(Terminal::size());
+            skip = skip / slices * slices;
+            draw_groups(tree, &groups, skip, slices, &message, None)?;
+        }
+
+        let key = match terminal.read_key(POLL)? {
+            Some(key) => key,
+            None => continue
+        };
+        message = Vec::new();
+        match key {
+            Key::PageDown | Key::Char('n') => match skip + slices < groups.len() {
+                true => skip += slices,
+                false => message = vec![String::from("No groups left")]
+            },
+            Key::PageUp | Key::Char('p') => match skip > 0 {
+                true => skip -= slices,
+                false => message = vec![String::from("Already on the first page")]
+            },
+            Key::Char(c @ '1'..='9') => {
+                let draw = |prompt: &str| draw_groups(tree, &groups, skip, slices, &message, Some(prompt));
+                let input = terminal.read_line(draw, "Choose group:", c.to_string())?.unwrap_or_default();
+                match input.trim().parse::<usize>() {
+                    Ok(n) if (1..=slices).contains(&n) && skip + n <= groups.len() => {
+                        message = process_group(&mut terminal, tree, &mut groups, skip + n - 1, skip,
+                            slices, settings)?;
+                        groups.retain(|g| g.files.len() > 1);
+                        skip = skip.min(groups.len().saturating_sub(1)) / slices * slices;
+                    },
+                    _ => message = vec![format!("There is no group {} on this page", input.trim())]
+                }
+            },
+            Key::Char('q') | Key::Escape | Key::Interrupt => return Ok(()),
+            _ => ()
+        }
+        size = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_content() {
+        let dir = std::env::temp_dir().join(format!("piecut-duplicates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &[u8]| {
+            fs::write(dir.join(name), content).unwrap();
+            dir.join(name)
+        };
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut changed = content.clone();
+        changed[150_000] ^= 1;
+        let (a, b, c) = (write("a", &content), write("b", &content), write("c", &changed));
+        let short = write("short", &content[..100_000]);
+
+        assert!(same_content(&a, &b).unwrap());
+        assert!(!same_content(&a, &c).unwrap());
+        assert!(!same_content(&a, &short).unwrap());
+        assert!(!same_content(&short, &a).unwrap());
+        assert!(same_content(&a, &dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+//! Hierarchical ignore files like `.gitignore`. Rules of deeper directories
+//! take precedence, within a directory later rules override earlier ones.
+
+use std::{fs, path, sync::Arc};
+
+use crate::pattern::Glob;
+
+/// Ignore files read in every directory, in increasing precedence.
+pub const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".piecutignore"];
+
+/// A single line of an ignore file.
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules of all ignore files of a single directory.
+struct Rules {
+    /// Directory the ignore files are located in, as it is seen while walking.
+    dir: path::PathBuf,
+    /// Path of `dir` relative to the directory the ignore files were read
+    /// from, only set for ignore files above the scanned directory.
+    prefix: path::PathBuf,
+    rules: Vec<Rule>,
+}
+
+/// Parses a line of an ignore file using the gitignore syntax.
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end_matches('\r');
+    if line.is_empty() || line.starts_with('#') {
+        return None
+    }
+    // trailing spaces are ignored unless escaped, other escapes like "\#" or
+    // "\!" are handled by the glob
+    let mut trimmed = line.trim_end_matches(' ');
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        trimmed = &line[..trimmed.len() + 1];
+    }
+    let (negated, line) = match trimmed.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed)
+    };
+    let dir_only = line.ends_with('/');
+    let line = line.trim_end_matches('/');
+    if line.is_empty() {
+        return None
+    }
+    Glob::new(line).ok().map(|glob| Rule { glob, negated, dir_only })
+}
+
+/// Reads the ignore files of a directory. Returns `None` if there aren't any.
+fn read_rules(dir: &path::Path, walked: &path::Path, prefix: path::PathBuf) -> Option<Rules> {
+    let mut rules = Vec::new();
+    for name in IGNORE_FILES.iter() {
+        if let Ok(content) = fs::read_to_string(dir.join(name)) {
+            rules.extend(content.lines().filter_map(parse_rule));
+        }
+    }
+    if rules.is_empty() {
+        return None
+    }
+    Some(Rules { dir: walked.to_path_buf(), prefix, rules })
+}
+
+impl Rules {
+    /// Returns whether the last matching rule ignores the path, `None` if no rule matches.
+    fn check(&self, path: &path::Path, is_dir: bool) -> Option<bool> {
+        let relative = self.prefix.join(path.strip_prefix(&self.dir).ok()?);
+        self.rules.iter().rev()
+            .find(|r| (is_dir || !r.dir_only) && r.glob.is_match(&relative))
+            .map(|r| !r.negated)
+    }
+}
+
+/// The ignore files that apply to the entries of a directory. Cheap to clone,
+/// so every directory that is walked can carry its own.
+#[derive(Clone)]
+pub struct Ignore {
+    /// Rules of the directory and its parents, the deepest last.
+    levels: Vec<Arc<Rules>>,
+    /// The directory itself is ignored, so everything in it is as well.
+    inside_ignored: bool,
+}
+
+impl Ignore {
+    /// Reads the ignore files of the parent directories of `root` up to the
+    /// top of the git repository it is located in.
+    pub fn new(root: &path::Path) -> Ignore {
+        let mut levels = Vec::new();
+        if let Ok(canonical) = fs::canonicalize(root) {
+            let mut ancestors = Vec::new();
+            for ancestor in canonical.ancestors().skip(1) {
+                ancestors.push(ancestor);
+                if ancestor.join(".git").exists() {
+                    break
+                }
+            }
+            if ancestors.last().is_some_and(|a| a.join(".git").exists()) {
+                for ancestor in ancestors.into_iter().rev() {
+                    let prefix = canonical.strip_prefix(ancestor).unwrap().to_path_buf();
+                    levels.extend(read_rules(ancestor, root, prefix).map(Arc::new));
+                }
+            }
+        }
+        Ignore { levels, inside_ignored: false }
+    }
+
+    /// Checks if an entry is ignored.
+    pub fn is_ignored(&self, path: &path::Path, is_dir: bool) -> bool {
+        self.inside_ignored || self.levels.iter().rev()
+            .find_map(|rules| rules.check(path, is_dir))
+            .unwrap_or(false)
+    }
+
+    /// Returns the rules for the entries of a directory, including its own
+    /// ignore files.
+    pub fn enter(&self, dir: &path::Path, ignored: bool) -> Ignore {
+        let mut entered = self.clone();
+        if ignored {
+            // the ignore files of an ignored directory don't matter
+            entered.inside_ignored = true;
+        } else if let Some(rules) = read_rules(dir, dir, path::PathBuf::new()) {
+            entered.levels.push(Arc::new(rules));
+        }
+        entered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(dir: &str, lines: &[&str]) -> Arc<Rules> {
+        Arc::new(Rules {
+            dir: path::PathBuf::from(dir),
+            prefix: path::PathBuf::new(),
+            rules: lines.iter().filter_map(|line| parse_rule(line)).collect()
+        })
+    }
+
+    fn matches(line: &str, name: &str) -> bool {
+        parse_rule(line).unwrap().glob.is_match(path::Path::new(name))
+    }
+
+    #[test]
+    fn parse_rules() {
+        for skipped in ["", "# comment", "/", "!", "\r"] {
+            assert!(parse_rule(skipped).is_none(), "{:?} is a rule", skipped);
+        }
+        let rule = parse_rule("!keep.log").unwrap();
+        assert!(rule.negated && !rule.dir_only);
+        let rule = parse_rule("build/").unwrap();
+        assert!(!rule.negated && rule.dir_only);
+        assert!(matches("build/", "build"));
+        assert!(matches("*.log\r", "a.log"));
+        assert!(matches("\\#notes", "#notes"));
+        assert!(matches("\\!important", "!important"));
+        // trailing spaces only count if escaped
+        assert!(matches("a.txt  ", "a.txt"));
+        assert!(matches("a\\ ", "a "));
+        assert!(!matches("a\\ ", "a"));
+    }
+
+    #[test]
+    fn precedence() {
+        let ignore = Ignore {
+            levels: vec![rules("root", &["*.log", "!keep.log", "build/"]), rules("root/sub", &["keep.log"])],
+            inside_ignored: false
+        };
+        let ignored = |path: &str, is_dir: bool| ignore.is_ignored(path::Path::new(path), is_dir);
+        assert!(ignored("root/a.log", false));
+        // later rules override earlier ones
+        assert!(!ignored("root/keep.log", false));
+        // deeper directories override their parents
+        assert!(ignored("root/sub/keep.log", false));
+        assert!(ignored("root/sub/other.log", false));
+        assert!(!ignored("root/a.txt", false));
+        assert!(ignored("root/build", true));
+        assert!(!ignored("root/build", false));
+
+        let inside = ignore.enter(path::Path::new("root/build"), true);
+        assert!(inside.is_ignored(path::Path::new("root/build/a.txt"), false));
+        let entered = ignore.enter(path::Path::new("root/src"), false);
+        assert!(!entered.is_ignored(path::Path::new("root/src/a.txt"), false));
+        assert!(entered.is_ignored(path::Path::new("root/src/a.log"), false));
+    }
+}
@@ -0,0 +1,100 @@
+//! Full metadata of a single entry, for telling apart files with the same name.
+
+use std::{fs, io, path, time};
+
+/// Formats a point in time in the local timezone, e.g. `2024-03-01 14:05:09`
+/// if `separator` is a space. Returns `None` for times before 1970.
+#[cfg(unix)]
+pub fn local_time(time: time::SystemTime, separator: char) -> Option<String> {
+    let secs = time.duration_since(time::UNIX_EPOCH).ok()?.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    Some(format!("{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}", tm.tm_year + 1900, tm.tm_mon + 1,
+        tm.tm_mday, separator, tm.tm_hour, tm.tm_min, tm.tm_sec))
+}
+
+#[cfg(unix)]
+fn format_time(time: io::Result<time::SystemTime>) -> String {
+    time.ok().and_then(|t| local_time(t, ' ')).unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Name of the user with `uid`, or the number if there is none.
+#[cfg(unix)]
+fn user_name(uid: u32) -> String {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    unsafe { libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+    match result.is_null() {
+        true => uid.to_string(),
+        false => unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) }.to_string_lossy().into_owned()
+    }
+}
+
+/// Name of the group with `gid`, or the number if there is none.
+#[cfg(unix)]
+fn group_name(gid: u32) -> String {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    unsafe { libc::getgrgid_r(gid, &mut group, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+    match result.is_null() {
+        true => gid.to_string(),
+        false => unsafe { std::ffi::CStr::from_ptr(group.gr_name) }.to_string_lossy().into_owned()
+    }
+}
+
+/// Permissions like `ls -l` shows them, e.g. `drwxr-xr-x (755)`.
+#[cfg(unix)]
+fn permissions(metadata: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+    let kind = match metadata.file_type() {
+        t if t.is_dir() => 'd',
+        t if t.is_symlink() => 'l',
+        _ => '-'
+    };
+    let bits: String = "rwxrwxrwx".chars().enumerate()
+        .map(|(i, c)| if mode & (0o400 >> i) != 0 { c } else { '-' })
+        .collect();
+    format!("{}{} ({:o})", kind, bits, mode & 0o7777)
+}
+
+/// Reads the metadata of `path` again and describes it line by line.
+#[cfg(unix)]
+pub fn describe(path: &path::Path) -> io::Result<Vec<String>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path)?;
+    let mut lines = vec![
+        format!("Path:        {}", path.display()),
+        format!("Owner:       {}:{}", user_name(metadata.uid()), group_name(metadata.gid())),
+        format!("Permissions: {}", permissions(&metadata)),
+        format!("Modified:    {}", format_time(metadata.modified())),
+        format!("Accessed:    {}", format_time(metadata.accessed())),
+        format!("Created:     {}", format_time(metadata.created())),
+        format!("Links:       {}", metadata.nlink()),
+    ];
+    if metadata.file_type().is_symlink() {
+        lines.insert(1, format!("Target:      {}", fs::read_link(path)?.display()));
+    }
+    Ok(lines)
+}
+
+/// Without owners and modes, only what the standard library knows is shown.
+#[cfg(not(unix))]
+pub fn describe(path: &path::Path) -> io::Result<Vec<String>> {
+    let metadata = fs::symlink_metadata(path)?;
+    let format_time = |time: io::Result<time::SystemTime>| match time.map(|t| t.duration_since(time::UNIX_EPOCH)) {
+        Ok(Ok(since)) => format!("{} seconds since 1970", since.as_secs()),
+        _ => String::from("unknown")
+    };
+    Ok(vec![
+        format!("Path:        {}", path.display()),
+        format!("Permissions: {}", if metadata.permissions().readonly() { "read-only" } else { "writable" }),
+        format!("Modified:    {}", format_time(metadata.modified())),
+        format!("Accessed:    {}", format_time(metadata.accessed())),
+        format!("Created:     {}", format_time(metadata.created())),
+    ])
+}
@@ -0,0 +1,317 @@
+//! Just enough JSON to write reports and read them back in.
+
+/// A parsed JSON value. Numbers are kept as written, so large sizes don't
+/// lose precision.
+#[derive(PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Looks up a key of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None
+        }
+    }
+}
+
+/// Quotes a string.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Joins already formatted values into an indented array.
+pub fn array(values: &[String], indent: &str) -> String {
+    if values.is_empty() {
+        return String::from("[]")
+    }
+    let separator = format!(",\n{}  ", indent);
+    format!("[\n{}  {}\n{}]", indent, values.join(&separator), indent)
+}
+
+/// Parses a JSON document.
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser { chars: input.chars().collect(), pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected trailing characters"))
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("invalid JSON at character {}: {}", self.pos, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)))
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        let end = self.pos + word.len();
+        if end <= self.chars.len() && self.chars[self.pos..end].iter().copied().eq(word.chars()) {
+            self.pos = end;
+            return Ok(value)
+        }
+        Err(self.error("unknown keyword"))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("expected a value"))
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(members))
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members))
+                },
+                _ => return Err(self.error("expected ',' or '}'"))
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(values))
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(values))
+                },
+                _ => return Err(self.error("expected ',' or ']'"))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break
+            }
+            self.pos += 1;
+        }
+        Ok(Value::Number(self.chars[start..self.pos].iter().collect()))
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, String> {
+        let end = self.pos + 4;
+        if end > self.chars.len() {
+            return Err(self.error("incomplete unicode escape"))
+        }
+        let digits: String = self.chars[self.pos..end].iter().collect();
+        self.pos = end;
+        u32::from_str_radix(&digits, 16).map_err(|_| self.error("invalid unicode escape"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some('"') {
+            return Err(self.error("expected a string"))
+        }
+        self.pos += 1;
+        let mut string = String::new();
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match escaped {
+                        '"' | '\\' | '/' => string.push(escaped),
+                        'b' => string.push('\u{8}'),
+                        'f' => string.push('\u{c}'),
+                        'n' => string.push('\n'),
+                        'r' => string.push('\r'),
+                        't' => string.push('\t'),
+                        'u' => {
+                            let mut code = self.hex_escape()?;
+                            // surrogate pairs encode characters outside the basic plane
+                            if (0xD800..0xDC00).contains(&code)
+                                    && self.chars.get(self.pos..self.pos + 2) == Some(&['\\', 'u']) {
+                                self.pos += 2;
+                                let low = self.hex_escape()?;
+                                match low {
+                                    0xDC00..=0xDFFF => code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00),
+                                    _ => {
+                                        string.push('\u{FFFD}');
+                                        code = low;
+                                    }
+                                }
+                            }
+                            string.push(std::char::from_u32(code).unwrap_or('\u{FFFD}'));
+                        },
+                        _ => return Err(self.error("invalid escape"))
+                    }
+                },
+                c => string.push(c)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        let value = parse(r#" { "path": "/tmp", "size": 18446744073709551615, "counted": false,
+            "files": [1, null, "a"], "empty": {} } "#).unwrap();
+        assert_eq!(value.get("path").and_then(Value::as_str), Some("/tmp"));
+        assert_eq!(value.get("size").and_then(Value::as_u64), Some(u64::MAX));
+        assert_eq!(value.get("counted").and_then(Value::as_bool), Some(false));
+        let files = value.get("files").and_then(Value::as_array).unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files[1] == Value::Null);
+        assert!(value.get("empty") == Some(&Value::Object(Vec::new())));
+        assert!(value.get("missing").is_none());
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let value = parse(r#""a\"b\\c\/d\n\té""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"b\\c/d\n\té"));
+    }
+
+    #[test]
+    fn parse_surrogate_pairs() {
+        assert_eq!(parse(r#""\ud83d\ude00""#).unwrap().as_str(), Some("😀"));
+        assert_eq!(parse(r#""x\uD834\uDD1Ey""#).unwrap().as_str(), Some("x𝄞y"));
+        assert_eq!(parse(r#""\u00e9""#).unwrap().as_str(), Some("é"));
+        // a lone surrogate isn't a character
+        assert_eq!(parse(r#""\ud83d""#).unwrap().as_str(), Some("\u{FFFD}"));
+        assert_eq!(parse(r#""\ud83d\u0041""#).unwrap().as_str(), Some("\u{FFFD}A"));
+        assert_eq!(parse(r#""\ude00x""#).unwrap().as_str(), Some("\u{FFFD}x"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse(r#"{"a" 1}"#).is_err());
+        assert!(parse(r#""unterminated"#).is_err());
+        assert!(parse(r#""\u12""#).is_err());
+        assert!(parse("true false").is_err());
+        assert!(parse("nul").is_err());
+    }
+
+    #[test]
+    fn quote_round_trip() {
+        let text = "tab\there \"quoted\" back\\slash\nline \u{1} 😀";
+        assert_eq!(quote("a\"b"), r#""a\"b""#);
+        assert_eq!(quote("\u{1}"), r#""\u0001""#);
+        assert_eq!(parse(&quote(text)).unwrap().as_str(), Some(text));
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(array(&[], ""), "[]");
+        assert_eq!(array(&[String::from("1"), String::from("2")], "  "), "[\n    1,\n    2\n  ]");
+    }
+}
@@ -0,0 +1,136 @@
+//! Shows the largest files found so far while the scan is still running, so
+//! huge files can be deleted before it's done.
+
+use std::{io, thread, time, error::Error};
+use piechart::{Color, Data};
+
+use crate::{LameFile, Removal, Settings, Confirmation, to_readable_size, remove_confirmed, draw_chart, slice_color};
+use crate::progress::Progress;
+use crate::tui::{Key, Terminal, POLL};
+
+/// The preview is only shown if the scan takes longer than this.
+const DELAY: time::Duration = time::Duration::from_secs(2);
+/// Minimum time between updates of the preview.
+const REFRESH: time::Duration = time::Duration::from_secs(3);
+
+/// Creates Piechart data for the largest files found so far.
+fn create_preview_data(files: &[LameFile], total_size: u64) -> Vec<Data> {
+    let mut data_size: u64 = 0;
+    let mut data = Vec::<Data>::new();
+    for (i, file) in files.iter().enumerate() {
+        data.push(Data {
+            label: format!("({}) {}", i + 1, file),
+            value: file.size as f32 / total_size as f32,
+            color: Some(slice_color(i)),
+            fill: '•'
+        });
+        data_size += file.size;
+    }
+    let other_size = total_size.saturating_sub(data_size);
+    data.push(Data {
+        label: format!("Other so far: {}", to_readable_size(other_size)),
+        value: other_size as f32 / total_size as f32,
+        color: Some(Color::RGB(100, 100, 100)),
+        fill: '-'
+    });
+    data
+}
+
+/// Draws the largest files found so far, followed by `prompt` or the help line.
+fn draw_preview(progress: &Progress, shown: &[LameFile], removed: &[(LameFile, Removal)], message: &[String],
+        prompt: Option<&str>) -> io::Result<()> {
+    let (files, bytes) = progress.totals();
+    let total_size = bytes.saturating_sub(removed.iter().map(|(f, _)| f.size).sum());
+    let mut screen = format!("Still scanning, found {} entries with {} so far.\n\n", files,
+        to_readable_size(total_size));
+    if total_size > 0 {
+        let mut chart = Vec::new();
+        draw_chart(&mut chart, &create_preview_data(shown, total_size))?;
+        screen.push_str(&String::from_utf8_lossy(&chart));
+    }
+    screen.push('\n');
+    for line in message {
+        screen.push_str(&format!("{}\n", line));
+    }
+    Terminal::draw(&screen, prompt, "1-9 delete  q quit  Esc hide the preview, or wait for the scan to finish")
+}
+
+/// Shows the preview until `is_done` returns true. Files deleted in the
+/// meantime are added to `removed`. Returns whether the user quit.
+pub fn run(progress: &Progress, is_done: impl Fn() -> bool, settings: &Settings,
+        removed: &mut Vec<(LameFile, Removal)>) -> Result<bool, Box<dyn Error>> {
+    let start = time::Instant::now();
+    while start.elapsed() < DELAY {
+        if is_done() {
+            return Ok(false)
+        }
+        thread::sleep(POLL / 4);
+    }
+    progress.pause();
+    let mut terminal = Terminal::enter()?;
+
+    // the files shown by the last update, which numbers refer to
+    let mut shown = Vec::<LameFile>::new();
+    let mut message = Vec::<String>::new();
+    let mut updated: Option<(u64, time::Instant)> = None;
+    let mut size = None;
+    while !is_done() {
+        let changes = progress.changes();
+        if updated.is_none_or(|(c, at)| c != changes && at.elapsed() >= REFRESH) {
+            shown = progress.largest(settings.slices());
+            updated = Some((changes, time::Instant::now()));
+            size = None;
+        }
+        if size != Some(Terminal::size()) {
+            size = Some(Terminal::size());
+            draw_preview(progress, &shown, removed, &message, None)?;
+        }
+
+        let key = match terminal.read_key(POLL)? {
+            Some(key) => key,
+            None => continue
+        };
+        message = Vec::new();
+        match key {
+            Key::Char('q') | Key::Interrupt => {
+                progress.cancel();
+                return Ok(true)
+            },
+            // the rest of the scan is waited for with the progress line
+            Key::Escape => {
+                drop(terminal);
+                progress.resume();
+                return Ok(false)
+            },
+            Key::Char(c @ '1'..='9') => {
+                let draw = |prompt: &str| draw_preview(progress, &shown, removed, &message, Some(prompt));
+                let input = terminal.read_line(draw, "Delete file:", c.to_string())?.unwrap_or_default();
+                let file = match input.trim().parse::<usize>() {
+                    Ok(n) if (1..=shown.len()).contains(&n) => shown[n - 1].clone(),
+                    _ => {
+                        message = vec![format!("There is no file {}", input.trim())];
+                        size = None;
+                        continue
+                    }
+                };
+                let confirmation = Confirmation::new(&file, settings);
+                message = confirmation.notes.clone();
+                let draw = |prompt: &str| draw_preview(progress, &shown, removed, &message, Some(prompt));
+                message = match terminal.confirm(draw, &confirmation)? {
+                    true => match remove_confirmed(&file, settings) {
+                        Ok((removal, description)) => {
+                            progress.forget(&file.path);
+                            removed.push((file, removal));
+                            vec![description]
+                        },
+                        Err(err) => vec![format!("Couldn't delete {}: {}", file.path.display(), err)]
+                    },
+                    false => Vec::new()
+                };
+            },
+            _ => ()
+        }
+        size = None;
+    }
+    Ok(false)
+}
@@ -1,73 +1,724 @@
-use std::{fmt, fs, time, io, io::Write, path, error::Error};
-use clap::{Arg, ArgMatches, App, AppSettings, crate_version, crate_name, crate_description};
-use walkdir::WalkDir;
+use std::{fmt, fs, time, io, path, error::Error, cmp, thread, collections::HashMap, collections::HashSet};
+use clap::{Arg, ArgMatches, App, AppSettings, SubCommand, crate_version, crate_name, crate_description};
 use piechart::{Chart, Color, Data};
 
+mod batch;
+mod cache;
+mod diff;
+mod duplicates;
+mod ignore;
+mod info;
+mod json;
+mod live;
+mod mounts;
+mod pattern;
+mod progress;
+mod report;
+mod trash;
+mod tui;
+mod walk;
+
 const SECONDS_PER_DAY: u64 = 86400;
+/// Number of slices if neither `--slices` nor the height of the terminal is known.
 const NUM_FILES_SHOWN: usize = 5;
 const SIZE_CONVERT_VALUE: f64 = 1024.;
-const SIZE_CONVERT_SUFFIXES: [&str; 5] = ["Byte", "KiB", "MiB", "GiB", "TiB"];
+const SIZE_CONVERT_SUFFIXES: [&str; 7] = ["Byte", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
 
-/// Files that are possible candidates for deletion.
+/// Files or directories that are possible candidates for deletion. Directories
+/// keep track of their children, so the scanned tree can be navigated.
+#[derive(Clone)]
 struct LameFile {
     size: u64,
+    /// Size according to the metadata if `size` is the space allocated on
+    /// disk, see `--disk-usage`.
+    apparent_size: Option<u64>,
     path: path::PathBuf,
+    is_dir: bool,
+    /// Number of hard links, the size is only counted for one of them.
+    links: u64,
+    /// Index of the data this file shares with others in `Tree::shared`.
+    shared: Option<usize>,
+    is_symlink: bool,
+    /// Reached through a followed symlink, so deleting it removes the target's content.
+    behind_symlink: bool,
+    modified: Option<time::SystemTime>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    deleted: bool,
+}
+
+impl LameFile {
+    /// Describes the file by its size, `name` and kind.
+    fn describe(&self, f: &mut impl fmt::Write, name: &str) -> fmt::Result {
+        write!(f, "{:>11}", to_readable_size(self.size))?;
+        if let Some(apparent_size) = self.apparent_size {
+            write!(f, " on disk, {:>11} apparent", to_readable_size(apparent_size))?;
+        }
+        write!(f, " -- {}", name)?;
+        if self.is_dir && self.is_symlink {
+            write!(f, " (linked dir)")?;
+        } else if self.is_dir {
+            write!(f, " (dir)")?;
+        } else if self.is_symlink {
+            write!(f, " (symlink)")?;
+        } else if self.links > 1 {
+            write!(f, " ({} links)", self.links)?;
+        }
+        Ok(())
+    }
+
+    /// Describes the file by its path relative to `root`, shortened in the
+    /// middle so the description takes at most `width` characters.
+    fn describe_relative(&self, root: &path::Path, width: usize) -> String {
+        let path = match self.path.strip_prefix(root) {
+            Ok(relative) if relative.as_os_str().is_empty() => path::Path::new("."),
+            Ok(relative) => relative,
+            Err(_) => &self.path
+        };
+        let mut rest = String::new();
+        let _ = self.describe(&mut rest, "\"\"");
+        let name = shorten_middle(&path.to_string_lossy(), width.saturating_sub(rest.chars().count()));
+        let mut description = String::new();
+        let _ = self.describe(&mut description, &format!("\"{}\"", name));
+        description
+    }
 }
 
 impl fmt::Display for LameFile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:>11} -- {:?}", to_readable_size(self.size),
-            self.path.file_name().unwrap())
+        self.describe(f, &format!("{:?}", self.path.file_name().unwrap_or_else(|| self.path.as_os_str())))
+    }
+}
+
+/// Data shared by several files, e.g. hard links. It's counted for one of
+/// them, the others have a size of 0.
+struct Shared {
+    size: u64,
+    apparent_size: Option<u64>,
+    /// Where the links are counted, the entry of a listed link or the
+    /// directory of a link that was filtered out.
+    links: Vec<usize>,
+    /// The link the data is counted for.
+    counted: usize,
+}
+
+/// All scanned entries. The scanned directory itself is the first entry,
+/// its size is the total size of everything below it.
+struct Tree {
+    entries: Vec<LameFile>,
+    /// Errors of entries that were skipped while scanning.
+    errors: Vec<String>,
+    shared: Vec<Shared>,
+}
+
+impl Tree {
+    /// Lists the entries below `dir`, highest size on top. Directories that are
+    /// `depth` levels below are listed with their accumulated size, without a
+    /// depth only files are listed.
+    fn list(&self, dir: usize, depth: Option<usize>) -> Vec<usize> {
+        let mut list = Vec::<usize>::new();
+        self.collect(dir, depth, &mut list);
+        list.sort_by_key(|&i| cmp::Reverse(self.entries[i].size));
+        list
+    }
+
+    fn collect(&self, dir: usize, depth: Option<usize>, list: &mut Vec<usize>) {
+        for &child in &self.entries[dir].children {
+            let entry = &self.entries[child];
+            if entry.deleted {
+                continue
+            }
+            match (entry.is_dir, depth) {
+                (false, _) | (true, Some(1)) => list.push(child),
+                (true, _) => self.collect(child, depth.map(|d| d - 1), list)
+            }
+        }
+    }
+
+    /// Whether `index` is `dir` itself or one of the entries below it.
+    fn is_below(&self, index: usize, dir: usize) -> bool {
+        let mut entry = Some(index);
+        while let Some(e) = entry {
+            if e == dir {
+                return true
+            }
+            entry = self.entries[e].parent;
+        }
+        false
+    }
+
+    /// Whether an entry or one of its parent directories was deleted.
+    fn is_removed(&self, index: usize) -> bool {
+        let mut entry = Some(index);
+        while let Some(e) = entry {
+            if self.entries[e].deleted {
+                return true
+            }
+            entry = self.entries[e].parent;
+        }
+        false
+    }
+
+    /// Adds to the size of an entry and its parent directories, up to the
+    /// first deleted one, whose size doesn't count towards its parent anymore.
+    fn grow(&mut self, index: usize, size: u64, apparent_size: Option<u64>) {
+        let mut entry = Some(index);
+        while let Some(e) = entry {
+            self.entries[e].size += size;
+            self.entries[e].apparent_size = self.entries[e].apparent_size
+                .zip(apparent_size).map(|(a, b)| a + b);
+            entry = self.entries[e].parent.filter(|_| !self.entries[e].deleted);
+        }
+    }
+
+    /// Reverts `grow`.
+    fn shrink(&mut self, index: usize, size: u64, apparent_size: Option<u64>) {
+        let mut entry = Some(index);
+        while let Some(e) = entry {
+            self.entries[e].size -= size;
+            self.entries[e].apparent_size = self.entries[e].apparent_size
+                .zip(apparent_size).map(|(a, b)| a - b);
+            entry = self.entries[e].parent.filter(|_| !self.entries[e].deleted);
+        }
+    }
+
+    /// Counts shared data for another of its links.
+    fn move_shared(&mut self, shared: usize, link: usize) {
+        let Shared { size, apparent_size, counted, .. } = self.shared[shared];
+        self.shrink(counted, size, apparent_size);
+        self.grow(link, size, apparent_size);
+        self.shared[shared].counted = link;
+    }
+
+    /// Marks an entry as deleted and removes its size from all parent
+    /// directories. Data of hard links that remain elsewhere isn't freed,
+    /// it's counted for one of the remaining links instead.
+    fn remove(&mut self, index: usize) {
+        for shared in 0..self.shared.len() {
+            let counted = self.shared[shared].counted;
+            if !self.is_below(counted, index) || self.is_removed(counted) {
+                continue
+            }
+            let remaining = self.shared[shared].links.iter()
+                .find(|&&l| !self.is_below(l, index) && !self.is_removed(l))
+                .copied();
+            if let Some(link) = remaining {
+                self.move_shared(shared, link);
+            }
+        }
+        let (size, apparent_size) = (self.entries[index].size, self.entries[index].apparent_size);
+        self.entries[index].deleted = true;
+        if let Some(parent) = self.entries[index].parent {
+            self.shrink(parent, size, apparent_size);
+        }
+    }
+
+    /// Reverts `remove` for a restored entry. Shared data that was removed
+    /// with all its links is counted again for a restored one.
+    fn restore(&mut self, index: usize) {
+        let (size, apparent_size) = (self.entries[index].size, self.entries[index].apparent_size);
+        self.entries[index].deleted = false;
+        if let Some(parent) = self.entries[index].parent {
+            self.grow(parent, size, apparent_size);
+        }
+        for shared in 0..self.shared.len() {
+            if !self.is_removed(self.shared[shared].counted) {
+                continue
+            }
+            let restored = self.shared[shared].links.iter()
+                .find(|&&l| !self.is_removed(l))
+                .copied();
+            if let Some(link) = restored {
+                self.move_shared(shared, link);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl Tree {
+    fn new() -> Tree {
+        Tree { entries: Vec::new(), errors: Vec::new(), shared: Vec::new() }
     }
+
+    /// Adds an entry below `parent` and counts its size for the parent directories.
+    fn push(&mut self, parent: Option<usize>, name: &str, size: u64, is_dir: bool) -> usize {
+        let index = self.entries.len();
+        let path = match parent {
+            Some(parent) => self.entries[parent].path.join(name),
+            None => path::PathBuf::from(name)
+        };
+        self.entries.push(LameFile {
+            size: 0,
+            apparent_size: None,
+            path,
+            is_dir,
+            links: 1,
+            shared: None,
+            is_symlink: false,
+            behind_symlink: false,
+            modified: None,
+            parent,
+            children: Vec::new(),
+            deleted: false
+        });
+        if let Some(parent) = parent {
+            self.entries[parent].children.push(index);
+        }
+        self.grow(index, size, None);
+        index
+    }
+}
+
+/// Conditions files have to meet to be listed. Times are given in seconds.
+struct Filters {
+    min_created: u64,
+    min_modified: u64,
+    min_accessed: u64,
+    /// Files have to match one of these globs, if there are any.
+    include: Vec<pattern::Glob>,
+    /// Files and directories matching one of these globs are skipped entirely.
+    exclude: Vec<pattern::Glob>,
+    /// Full paths have to match one of these expressions, if there are any.
+    regex: Vec<pattern::Regex>,
+    /// How entries matched by ignore files like `.gitignore` are treated.
+    ignore_files: IgnoreFiles,
+    /// Directories on other filesystems than the scanned one are skipped.
+    one_file_system: bool,
+    /// Filesystems of these types are skipped, e.g. `proc` or `nfs`.
+    skip_fs_types: Vec<String>,
+    /// Symlinks are followed instead of being listed as links.
+    follow_symlinks: bool,
+    /// Files have to be at least this large.
+    min_size: u64,
+    /// Files must not be larger than this, if set.
+    max_size: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum IgnoreFiles {
+    /// Ignore files aren't read.
+    Off,
+    /// Ignored files and directories are skipped entirely.
+    Skip,
+    /// Only ignored files are shown.
+    Only,
+}
+
+impl Filters {
+    /// Describes the active filters, e.g. "created at least 3 days ago".
+    fn describe(&self) -> Vec<String> {
+        let times = [
+            ("created", self.min_created),
+            ("modified", self.min_modified),
+            ("accessed", self.min_accessed)
+        ];
+        let mut active: Vec<String> = times.iter()
+            .filter(|(_, min)| *min > 0)
+            .map(|(name, min)| format!("{} at least {} days ago", name, min / SECONDS_PER_DAY))
+            .collect();
+        let join = |patterns: Vec<String>| patterns.join(" or ");
+        if !self.include.is_empty() {
+            active.push(format!("matching {}", join(self.include.iter().map(|g| g.to_string()).collect())));
+        }
+        if !self.regex.is_empty() {
+            active.push(format!("with paths matching {}", join(self.regex.iter().map(|r| r.to_string()).collect())));
+        }
+        if self.ignore_files == IgnoreFiles::Only {
+            active.push(format!("ignored by {}", ignore::IGNORE_FILES.join(", ")));
+        }
+        if self.min_size > 0 {
+            active.push(format!("of at least {}", to_readable_size(self.min_size)));
+        }
+        if let Some(max_size) = self.max_size {
+            active.push(format!("of at most {}", to_readable_size(max_size)));
+        }
+        active.extend(self.describe_skipped());
+        active
+    }
+
+    /// Describes the filters that skip entries entirely, which aren't in the
+    /// cache of the scan either.
+    fn describe_skipped(&self) -> Vec<String> {
+        let mut active = Vec::new();
+        let join = |patterns: Vec<String>| patterns.join(" or ");
+        if !self.exclude.is_empty() {
+            active.push(format!("not matching {}", join(self.exclude.iter().map(|g| g.to_string()).collect())));
+        }
+        if self.ignore_files == IgnoreFiles::Skip {
+            active.push(format!("not ignored by {}", ignore::IGNORE_FILES.join(", ")));
+        }
+        if self.one_file_system {
+            active.push(String::from("on the same filesystem"));
+        }
+        if !self.skip_fs_types.is_empty() {
+            active.push(format!("not on {} filesystems", join(self.skip_fs_types.clone())));
+        }
+        active
+    }
+
+    /// Describes everything that changes which entries the scan reads, so
+    /// only scans that read the same entries are compared.
+    fn describe_walk(&self) -> Vec<String> {
+        let mut walk = self.describe_skipped();
+        if self.follow_symlinks {
+            walk.push(String::from("following symlinks"));
+        }
+        walk
+    }
+
+    /// Checks the include globs and regular expressions of a file.
+    fn matches_patterns(&self, path: &path::Path, relative: &path::Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|g| g.is_match(relative)))
+            && (self.regex.is_empty()
+                || self.regex.iter().any(|r| r.is_match(&path.to_string_lossy())))
+    }
+
+    /// Checks if an entry is excluded, which also skips the content of directories.
+    fn is_excluded(&self, relative: &path::Path) -> bool {
+        self.exclude.iter().any(|g| g.is_match(relative))
+    }
+}
+
+/// Settings of the interactive session.
+struct Settings {
+    /// Depth at which directories are shown, `None` shows single files.
+    depth: Option<usize>,
+    /// Move deleted entries to the trash instead of removing them.
+    trash: bool,
+    /// Only pretend to delete entries.
+    dry_run: bool,
+    /// Compare with an earlier scan and only show what grew since then.
+    diff: Option<diff::Diff>,
+    /// Number of entries shown at once, `None` fits them to the terminal.
+    slices: Option<usize>,
+    /// Show paths relative to the scanned directory instead of file names.
+    relative_paths: bool,
+}
+
+impl Settings {
+    /// Number of entries shown at once. Without `--slices` it follows the
+    /// height of the terminal, which might have been resized.
+    fn slices(&self) -> usize {
+        self.slices.or_else(tui::automatic_slices).unwrap_or(NUM_FILES_SHOWN)
+    }
+}
+
+/// How an entry was removed from the disk.
+enum Removal {
+    Trashed(trash::Trashed),
+    Deleted,
+    /// Nothing was removed in a dry run.
+    Simulated,
+}
+
+/// A directory the user navigated into, with its listed entries, the
+/// number of entries skipped by paging and the selected entry on the page.
+struct View {
+    dir: usize,
+    entries: Vec<usize>,
+    skip: usize,
+    selected: usize,
+}
+
+impl View {
+    fn new(tree: &Tree, dir: usize, settings: &Settings) -> View {
+        View {
+            dir,
+            entries: View::list(tree, dir, settings),
+            skip: 0,
+            selected: 0
+        }
+    }
+
+    /// Lists the entries below `dir` by size, or by growth when comparing scans.
+    fn list(tree: &Tree, dir: usize, settings: &Settings) -> Vec<usize> {
+        match &settings.diff {
+            Some(diff) => diff.list(tree, dir, settings.depth),
+            None => tree.list(dir, settings.depth)
+        }
+    }
+
+    /// Sorts the listed entries again after the data of hard links is counted
+    /// for another link. Deleted entries stay listed, so they can be restored.
+    fn sort(&mut self, tree: &Tree, settings: &Settings) {
+        let size = |&i: &usize| match &settings.diff {
+            Some(diff) => diff.growth(&tree.entries[i]),
+            None => tree.entries[i].size
+        };
+        self.entries.sort_by_key(|i| cmp::Reverse(size(i)));
+    }
+}
+
+/// Color of the slice at a position of the page. The first ones are the basic
+/// terminal colors, further ones are spread over the color wheel.
+fn slice_color(position: usize) -> Color {
+    if position < 6 {
+        return Color::Fixed(position as u8 + 1)
+    }
+    // the golden angle keeps neighbouring hues apart
+    let hue = (position as f32 * 137.5) % 360.0 / 60.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x)
+    };
+    let scale = |c: f32| (60.0 + c * 170.0) as u8;
+    Color::RGB(scale(r), scale(g), scale(b))
+}
+
+/// Draws a pie chart with a legend. The chart grows with the number of
+/// slices, so there is a row for every label.
+fn draw_chart(out: impl io::Write, data: &[Data]) -> io::Result<()> {
+    Chart::new()
+        .radius((data.len() as u16).saturating_sub(1).max(6))
+        .aspect_ratio(3)
+        .legend(true)
+        .draw_into(out, data)
+}
+
+/// Width of the pie chart and the space before its legend for a legend of
+/// `labels` labels, see `draw_chart`.
+fn chart_width(labels: usize) -> usize {
+    let radius = labels.saturating_sub(1).max(6) as f32;
+    2 * (radius * 3f32.sqrt()).round() as usize + 3
+}
+
+/// Cuts the middle out of `text` to make it at most `width` characters long,
+/// the start and the end of a path tell the most about it.
+fn shorten_middle(text: &str, width: usize) -> String {
+    let length = text.chars().count();
+    if length <= width {
+        return text.to_string()
+    }
+    let kept = width.saturating_sub(1);
+    let start: String = text.chars().take(kept / 2).collect();
+    let end: String = text.chars().skip(length - (kept - kept / 2)).collect();
+    format!("{}…{}", start, end)
 }
 
 /// Converts byte values to KiB, MiB, ...
 fn to_readable_size(size: u64) -> String {
     let base = (size.max(1) as f64).log(SIZE_CONVERT_VALUE);
     let floored = base.floor();
-    let result = SIZE_CONVERT_VALUE.powf(base - floored);
+    let result = match size {
+        0 => 0.0,
+        _ => SIZE_CONVERT_VALUE.powf(base - floored)
+    };
     format!("{:.2} {}", result, SIZE_CONVERT_SUFFIXES[floored as usize])
 }
 
+/// Parses a human readable size like "100M", "1.5 GiB", "500kB" or "12 Byte",
+/// which includes everything `to_readable_size` prints. Binary units are used
+/// for single letters, SI units for kB, MB, GB, TB, PB and EB.
+fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let factor: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" | "byte" | "bytes" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "p" | "pib" => 1 << 50,
+        "e" | "eib" => 1 << 60,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "pb" => 1_000_000_000_000_000,
+        "eb" => 1_000_000_000_000_000_000,
+        _ => return Err(format!("{} is not a valid size, e.g. 100M, 1.5GiB or 500kB", text))
+    };
+    let number = number.parse::<f64>()
+        .map_err(|_| format!("{} is not a valid size, e.g. 100M, 1.5GiB or 500kB", text))?;
+    Ok((number * factor as f64) as u64)
+}
+
 /// Parses command line arguments.
 fn parse_args<'a>() -> ArgMatches<'a> {
     App::new(crate_name!())
         .version(crate_version!())
         .setting(AppSettings::ColoredHelp)
         .about(crate_description!())
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("DIR")
             .help("Directory that contains the input files")
-            .required(true)
-        ).arg(Arg::with_name("created")
+            .required_unless("delete-from")
+        ).arg(Arg::with_name("duplicates")
+            .long("duplicates")
+            .help("Show groups of files with identical content")
+            .conflicts_with("dirs")
+        ).arg(Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .help("Print a report in the given format instead of starting the interactive mode")
+            .takes_value(true)
+            .possible_values(&["json", "csv", "text"])
+            .conflicts_with_all(&["duplicates", "dry-run"])
+        ).arg(Arg::with_name("top")
+            .long("top")
+            .value_name("N")
+            .help("Only include the N largest entries in the report")
+            .takes_value(true)
+            .requires("format")
+        ).arg(Arg::with_name("delete-from")
+            .long("delete-from")
+            .value_name("FILE")
+            .help("Delete the files listed in FILE, one path per line or a JSON report, \
+                use - to read from stdin")
+            .takes_value(true)
+            .conflicts_with_all(&["DIR", "format", "duplicates"])
+        )
+        .args(&scan_args())
+        .subcommand(SubCommand::with_name("diff")
+            .about("Shows what grew since an earlier scan")
+            .setting(AppSettings::ColoredHelp)
+            .arg(Arg::with_name("DIR")
+                .help("Directory that contains the input files")
+                .required(true)
+            ).arg(Arg::with_name("REPORT")
+                .help("JSON report of the earlier scan, defaults to the last scan of DIR")
+            )
+            .args(&scan_args())
+        )
+        .get_matches()
+}
+
+/// Arguments of the scan, shared by all modes that scan a directory.
+fn scan_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("created")
             .short("c")
             .long("min-created")
             .value_name("DAYS")
             .help("Creation date must be at least DAYS in the past")
-            .takes_value(true)
-        ).arg(Arg::with_name("modified")
+            .takes_value(true),
+        Arg::with_name("modified")
             .short("m")
             .long("min-modified")
             .value_name("DAYS")
             .help("Last modification date must be at least DAYS in the past")
-            .takes_value(true)
-        ).arg(Arg::with_name("accessed")
+            .takes_value(true),
+        Arg::with_name("accessed")
             .short("a")
             .long("min-accessed")
             .value_name("DAYS")
             .help("Last access date must be at least DAYS in the past")
+            .takes_value(true),
+        Arg::with_name("min-size")
+            .long("min-size")
+            .value_name("SIZE")
+            .help("Only show files of at least SIZE, e.g. 100M, 1.5GiB or 500kB")
+            .takes_value(true),
+        Arg::with_name("max-size")
+            .long("max-size")
+            .value_name("SIZE")
+            .help("Only show files of at most SIZE, e.g. 2G")
+            .takes_value(true),
+        Arg::with_name("dirs")
+            .short("d")
+            .long("dirs")
+            .help("Show directories with their accumulated size instead of single files"),
+        Arg::with_name("depth")
+            .long("depth")
+            .value_name("N")
+            .help("Directory depth below DIR at which sizes are accumulated [default: 1]")
             .takes_value(true)
-        )
-        .get_matches()
+            .requires("dirs"),
+        Arg::with_name("trash")
+            .long("trash")
+            .help("Move deleted files to the trash (default on Unix)")
+            .overrides_with("no-trash"),
+        Arg::with_name("no-trash")
+            .long("no-trash")
+            .help("Delete files permanently instead of moving them to the trash")
+            .overrides_with("trash"),
+        Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Only pretend to delete files and show what would have been reclaimed"),
+        Arg::with_name("include")
+            .short("i")
+            .long("include")
+            .value_name("GLOB")
+            .help("Only show files matching GLOB, e.g. '*.log' (can be repeated)")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("exclude")
+            .short("e")
+            .long("exclude")
+            .value_name("GLOB")
+            .help("Skip files and directories matching GLOB, e.g. '.git' (can be repeated)")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("regex")
+            .short("r")
+            .long("regex")
+            .value_name("REGEX")
+            .help("Only show files whose full path matches REGEX (can be repeated)")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("ignore-files")
+            .long("ignore-files")
+            .help("Skip files and directories ignored by .gitignore, .ignore or .piecutignore"),
+        Arg::with_name("only-ignored")
+            .long("only-ignored")
+            .help("Only show files ignored by .gitignore, .ignore or .piecutignore")
+            .conflicts_with("ignore-files"),
+        Arg::with_name("threads")
+            .short("j")
+            .long("threads")
+            .value_name("N")
+            .help("Number of threads scanning directories, defaults to the number of CPUs")
+            .takes_value(true),
+        Arg::with_name("no-cache")
+            .long("no-cache")
+            .help("Read all directories again instead of taking unchanged ones from the last scan. \
+                Files that grew in place in an unchanged directory keep their old size otherwise"),
+        Arg::with_name("one-file-system")
+            .short("x")
+            .long("one-file-system")
+            .help("Don't descend into directories on other filesystems, e.g. mounted drives"),
+        Arg::with_name("skip-fs-types")
+            .long("skip-fs-types")
+            .value_name("TYPES")
+            .help("Skip filesystems of these comma separated types, e.g. proc,sysfs,nfs")
+            .takes_value(true),
+        Arg::with_name("follow-symlinks")
+            .short("L")
+            .long("follow-symlinks")
+            .help("Scan the targets of symlinks, links back to a parent directory are skipped"),
+        Arg::with_name("slices")
+            .long("slices")
+            .value_name("N")
+            .help("Number of entries shown at once, defaults to what fits the terminal")
+            .takes_value(true),
+        Arg::with_name("disk-usage")
+            .long("disk-usage")
+            .help("Measure files by the space allocated on disk instead of their apparent size"),
+        Arg::with_name("relative-paths")
+            .short("p")
+            .long("relative-paths")
+            .help("Show paths relative to DIR instead of file names, shortened to fit the terminal")
+    ]
 }
 
 /// Checks if file metadata times (e.g last accessed) is not too
-/// far back in the past.
+/// far back in the past. Times the platform doesn't provide never are.
 fn meets_time_condition(now: time::SystemTime, min_value: u64,
-        actual_value: time::SystemTime) -> bool {
+        actual_value: Option<time::SystemTime>) -> bool {
     if min_value == 0 {
         return true
     }
-    if let Ok(dur) = now.duration_since(actual_value) {
+    if let Some(Ok(dur)) = actual_value.map(|actual| now.duration_since(actual)) {
         if dur.as_secs() > min_value {
             return true
         }
@@ -75,188 +726,724 @@ fn meets_time_condition(now: time::SystemTime, min_value: u64,
     false
 }
 
+/// A file or directory found by the scan. Files that don't match the filters
+/// only count towards the size of their directory, so only their size is kept.
+enum Scanned {
+    Entry(LameFile),
+    Size {
+        size: u64,
+        apparent_size: Option<u64>,
+        /// A symlink or reached through one.
+        through_symlink: bool,
+    },
+}
+
+impl Scanned {
+    fn size(&self) -> (u64, Option<u64>) {
+        match self {
+            Scanned::Entry(file) => (file.size, file.apparent_size),
+            Scanned::Size { size, apparent_size, .. } => (*size, *apparent_size)
+        }
+    }
+
+    fn through_symlink(&self) -> bool {
+        match self {
+            Scanned::Entry(file) => file.is_symlink || file.behind_symlink,
+            Scanned::Size { through_symlink, .. } => *through_symlink
+        }
+    }
+}
+
 /// Finds all files recursively within a directory. The files can be filtered by
-/// various criteria, e.g. last accessed date. See above.
-fn get_lame_files(path: &str, min_created: u64, min_modified: u64, min_accessed: u64)
-        -> Result<(u64, Vec<LameFile>), Box<dyn Error>> {
+/// various criteria, e.g. last accessed date. See above. Files that are filtered
+/// out still count towards the size of their directories. Unless `use_cache` is
+/// false, directories that didn't change since the last scan aren't read again.
+/// With `disk_usage`, sizes are the space allocated on disk.
+fn get_lame_files(path: &str, filters: &Filters, threads: usize, use_cache: bool,
+        disk_usage: bool, progress: &progress::Progress) -> Result<Tree, Box<dyn Error>> {
 
     let now = time::SystemTime::now();
-    let mut files = Vec::<LameFile>::new();
-    let mut total_size: u64 = 0;
-
-    for entry in WalkDir::new(path).into_iter() {
-        match entry {
-            Ok(file) => {
-                let metadata = file.metadata()?;
-                let size = metadata.len();
-                if metadata.is_file() 
-                        && meets_time_condition(now, min_created, metadata.created()?)
-                        && meets_time_condition(now, min_modified, metadata.modified()?)
-                        && meets_time_condition(now, min_accessed, metadata.accessed()?) {
-                    files.push(LameFile {
-                        size,
-                        path: file.path().to_path_buf()
-                    });
+    let mut tree = Tree { entries: Vec::new(), errors: Vec::new(), shared: Vec::new() };
+    // directories leading to the current entry, by depth
+    let mut parents = Vec::<usize>::new();
+
+    let root = path::Path::new(path);
+    let ignore = match filters.ignore_files {
+        IgnoreFiles::Off => None,
+        _ => Some(ignore::Ignore::new(root))
+    };
+
+    let previous = if use_cache { cache::load(root) } else { None };
+    // mount points are absolute, so they are compared with absolute paths
+    let absolute_root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let skipped_mounts = mounts::mount_points(&filters.skip_fs_types);
+    let root_device = fs::metadata(root).ok()
+        .and_then(|m| walk::Stat::from(&m).inode)
+        .map(|(dev, _)| dev);
+
+    let follow = filters.follow_symlinks;
+    // directories keep the ignore rules that apply in them and whether they
+    // are reached through a symlink
+    let state = (ignore, false);
+    let (walked, cache) = walk::walk(root, threads, follow, previous.as_ref(), state, |path, depth, stat, state| {
+        let (ignore, behind_symlink) = state;
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        if progress.is_cancelled() || (depth > 0 && filters.is_excluded(relative)) {
+            return Ok(walk::Visit::Skip)
+        }
+        if depth > 0 && stat.is_dir {
+            let other_device = stat.inode.map(|(dev, _)| dev) != root_device;
+            if (filters.one_file_system && other_device)
+                    || skipped_mounts.contains(&absolute_root.join(relative)) {
+                return Ok(walk::Visit::Skip)
+            }
+        }
+        let ignored = ignore.as_ref().is_some_and(|i| i.is_ignored(path, stat.is_dir));
+        if ignored && filters.ignore_files == IgnoreFiles::Skip && depth > 0 {
+            return Ok(walk::Visit::Skip)
+        }
+        let size = if disk_usage { stat.allocated } else { stat.size };
+        let apparent_size = if disk_usage { Some(stat.size) } else { None };
+        progress.visit(path, size);
+        let matches = stat.is_dir || depth == 0 || ((stat.is_file || stat.is_symlink)
+            && filters.matches_patterns(path, relative)
+            && (filters.ignore_files != IgnoreFiles::Only || ignored)
+            && size >= filters.min_size
+            && filters.max_size.is_none_or(|max| size <= max)
+            && meets_time_condition(now, filters.min_created, stat.created)
+            && meets_time_condition(now, filters.min_modified, stat.modified)
+            && meets_time_condition(now, filters.min_accessed, stat.accessed));
+        // followed symlinks share the data of their target like hard links
+        let inode = stat.inode.filter(|_| !stat.is_dir && (stat.links > 1 || follow));
+        if !matches {
+            let through_symlink = *behind_symlink || stat.is_symlink;
+            return Ok(walk::Visit::File((Scanned::Size { size, apparent_size, through_symlink }, inode)))
+        }
+        let file = LameFile {
+            size,
+            apparent_size,
+            path: path.to_path_buf(),
+            is_dir: stat.is_dir,
+            links: stat.links,
+            shared: None,
+            is_symlink: stat.is_symlink,
+            behind_symlink: *behind_symlink,
+            modified: stat.modified,
+            parent: None,
+            children: Vec::new(),
+            deleted: false
+        };
+        if stat.is_dir {
+            let state = (ignore.as_ref().map(|i| i.enter(path, ignored)), *behind_symlink || stat.is_symlink);
+            return Ok(walk::Visit::Dir((Scanned::Entry(file), None), state))
+        }
+        progress.found(&file);
+        Ok(walk::Visit::File((Scanned::Entry(file), inode)))
+    });
+    progress.finish();
+    // a cancelled scan didn't see everything, and there is nothing to keep
+    // if the directory couldn't be read at all
+    if !progress.is_cancelled() && walked.first().is_some_and(|(_, root)| root.is_ok()) {
+        if let Err(err) = cache::save(root, &cache, &filters.describe_walk()) {
+            eprintln!("Couldn't save the scan cache: {}\n", err);
+        }
+    }
+
+    // hard linked files that were already counted, and files that are found
+    // without going through a symlink, which are preferred over links to them
+    let mut linked = HashSet::<(u64, u64)>::new();
+    let mut groups = HashMap::<(u64, u64), usize>::new();
+    let real: HashSet<(u64, u64)> = walked.iter()
+        .filter_map(|(_, entry)| entry.as_ref().ok())
+        .filter(|(scanned, _)| !scanned.through_symlink())
+        .filter_map(|(_, inode)| *inode)
+        .collect();
+    for (depth, entry) in walked {
+        let (scanned, inode) = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                tree.errors.push(error);
+                continue
+            }
+        };
+        parents.truncate(depth);
+        let parent = parents.last().copied();
+        // all links share the same data, it's counted only once
+        let counted = match inode {
+            Some(inode) if scanned.through_symlink() => !real.contains(&inode) && linked.insert(inode),
+            Some(inode) => linked.insert(inode),
+            None => true
+        };
+        let (data_size, data_apparent_size) = scanned.size();
+        let (size, apparent_size) = match counted {
+            true => (data_size, data_apparent_size),
+            false => (0, data_apparent_size.map(|_| 0))
+        };
+        // links whose data is counted for another one don't meet a minimum size
+        let listed = match scanned {
+            Scanned::Entry(file) if file.is_dir || parent.is_none() || size >= filters.min_size => Some(file),
+            _ => None
+        };
+        // where the link is counted, see `Shared`
+        let link = if listed.is_some() { tree.entries.len() } else { parent.unwrap() };
+        let shared = inode.map(|inode| {
+            let shared = *groups.entry(inode).or_insert_with(|| {
+                tree.shared.push(Shared {
+                    size: data_size,
+                    apparent_size: data_apparent_size,
+                    links: Vec::new(),
+                    counted: link
+                });
+                tree.shared.len() - 1
+            });
+            tree.shared[shared].links.push(link);
+            if counted {
+                tree.shared[shared].counted = link;
+            }
+            shared
+        });
+        match listed {
+            Some(mut file) => {
+                let index = tree.entries.len();
+                file.size = size;
+                file.apparent_size = apparent_size;
+                file.shared = shared;
+                file.parent = parent;
+                if let Some(p) = parent {
+                    tree.entries[p].children.push(index);
+                }
+                if file.is_dir {
+                    parents.push(index);
                 }
-                total_size += size;
+                tree.entries.push(file);
             },
-            Err(error) => println!("{}. Skipping...", error)
+            None => {
+                tree.entries[link].size += size;
+                tree.entries[link].apparent_size = tree.entries[link].apparent_size
+                    .zip(apparent_size).map(|(a, b)| a + b);
+            }
+        }
+    }
+
+    // without the scanned directory itself there is nothing to show
+    if tree.entries.is_empty() {
+        return Err(tree.errors.pop().unwrap_or_else(|| format!("Couldn't scan {}", path)).into())
+    }
+
+    // children always come after their parents, so sizes and matches can be
+    // accumulated bottom up
+    let mut has_files: Vec<bool> = tree.entries.iter().map(|e| !e.is_dir).collect();
+    for i in (1..tree.entries.len()).rev() {
+        let parent = tree.entries[i].parent.unwrap();
+        tree.entries[parent].size += tree.entries[i].size;
+        tree.entries[parent].apparent_size = tree.entries[parent].apparent_size
+            .zip(tree.entries[i].apparent_size).map(|(a, b)| a + b);
+        has_files[parent] |= has_files[i];
+    }
+    // hide directories without any matching files
+    for entry in tree.entries.iter_mut() {
+        entry.children.retain(|&c| has_files[c]);
+    }
+
+    Ok(tree)
+}
+
+/// What has to be confirmed before an entry is deleted. Directories are
+/// removed recursively, which has to be confirmed by typing "yes". Symlinks
+/// are removed without their target, entries behind a followed symlink are
+/// removed in the target.
+struct Confirmation {
+    /// Things to know before deleting, e.g. about hard links.
+    notes: Vec<String>,
+    question: String,
+    /// "yes" has to be typed instead of "y".
+    type_yes: bool,
+}
+
+/// Warns that removing one of several hard links of a file frees nothing.
+fn hard_link_note(path: &path::Path, links: u64) -> String {
+    format!("{} has {} hard links. Removing this one won't free any space while the others remain.",
+        path.display(), links)
+}
+
+impl Confirmation {
+    fn new(file: &LameFile, settings: &Settings) -> Confirmation {
+        let path = &file.path;
+        let target = if settings.trash { " to trash" } else { "" };
+        let action = if settings.trash { "Move" } else { "Delete" };
+        let mut notes = Vec::new();
+        if file.behind_symlink {
+            let resolved = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+            notes.push(format!("{} is reached through a symlink, this removes {} itself.",
+                path.display(), resolved.display()));
+        }
+        if !file.is_dir && !file.is_symlink && file.links > 1 {
+            notes.push(hard_link_note(path, file.links));
+        }
+        let question = if file.is_symlink {
+            let link_target = fs::read_link(path)
+                .map(|t| t.display().to_string())
+                .unwrap_or_else(|_| String::from("its target"));
+            format!("{} symlink {}{}? Only the link is removed, {} is kept. y/N:",
+                action, path.display(), target, link_target)
+        } else if file.is_dir {
+            format!("{} directory {} and everything in it ({}){}? Type yes to confirm:",
+                action, path.display(), to_readable_size(file.size), target)
+        } else {
+            format!("{} file {}{}? y/N:", action, path.display(), target)
+        };
+        Confirmation { notes, question, type_yes: file.is_dir && !file.is_symlink }
+    }
+
+    /// Confirms the deletion of several entries at once with their combined
+    /// size. Directories among them have to be confirmed by typing "yes".
+    fn for_entries(files: &[&LameFile], settings: &Settings) -> Confirmation {
+        if let [file] = files {
+            return Confirmation::new(file, settings)
+        }
+        let target = if settings.trash { " to trash" } else { "" };
+        let action = if settings.trash { "Move" } else { "Delete" };
+        let mut notes: Vec<String> = files.iter()
+            .map(|f| format!("{:>11}  {}", to_readable_size(f.size), f.path.display()))
+            .collect();
+        notes.extend(files.iter().flat_map(|f| Confirmation::new(f, settings).notes));
+        let size = to_readable_size(files.iter().map(|f| f.size).sum());
+        let dirs = files.iter().filter(|f| f.is_dir && !f.is_symlink).count();
+        let question = match dirs {
+            0 => format!("{} these {} entries ({}){}? y/N:", action, files.len(), size, target),
+            _ => format!("{} these {} entries ({}){}, including {} directories and everything in them? \
+                Type yes to confirm:", action, files.len(), size, target, dirs)
         };
+        Confirmation { notes, question, type_yes: dirs > 0 }
     }
 
-    // sort by size, highest on top
-    files.sort_by(|a, b| b.size.cmp(&a.size));
+    fn is_confirmed(&self, answer: &str) -> bool {
+        let answer = answer.trim().to_uppercase();
+        if self.type_yes { answer == "YES" } else { answer == "Y" }
+    }
+}
 
-    Ok((total_size, files))
+/// Removes an entry after the deletion was confirmed and describes what
+/// happened to it.
+fn remove_confirmed(file: &LameFile, settings: &Settings) -> io::Result<(Removal, String)> {
+    let kind = match (file.is_symlink, file.is_dir) {
+        (true, _) => "Symlink",
+        (_, true) => "Directory",
+        _ => "File"
+    };
+    let removal = delete_file(&file.path, file.is_dir && !file.is_symlink, settings)?;
+    let message = match &removal {
+        Removal::Trashed(trashed) => format!("{} moved to trash: {}", kind, trashed.file.display()),
+        Removal::Deleted => format!("{} deleted", kind),
+        Removal::Simulated => format!("{} marked for deletion (dry run)", kind)
+    };
+    Ok((removal, message))
 }
 
-/// Prompts for file deletion.
-fn confirm_file_deletion(path: &path::Path) -> Result<bool, Box<dyn Error>>{
-    let mut choice = String::new();
-    print!("Delete file {}? y/N: ", path.to_str().unwrap());
-    io::stdout().flush()?;
-    io::stdin().read_line(&mut choice)?;
-    if choice.trim().to_uppercase() == "Y" {
+/// Deletes a file or directory without asking, or moves it to the trash
+/// depending on the settings. Nothing is touched in a dry run.
+fn delete_file(path: &path::Path, is_dir: bool, settings: &Settings) -> io::Result<Removal> {
+    if settings.dry_run {
+        return Ok(Removal::Simulated)
+    }
+    if settings.trash {
+        return Ok(Removal::Trashed(trash::move_to_trash(path)?))
+    }
+    if is_dir {
+        fs::remove_dir_all(path)?;
+    } else {
         fs::remove_file(path)?;
-        println!("File deleted\n");
-        return Ok(true)
     }
-    println!();
-    Ok(false)
+    Ok(Removal::Deleted)
 }
 
-/// Creates Piechart data for current pile slices.
-fn create_current_data(files: &Vec<LameFile>, skip: usize,
-        total_size: u64, deleted: &[bool; NUM_FILES_SHOWN]) -> Vec<Data> {    
+/// Creates Piechart data for current pile slices. When comparing scans, the
+/// slices show how much entries grew and what was deleted since then. Returns
+/// no data if there is nothing to show.
+fn create_current_data(tree: &Tree, view: &View, settings: &Settings, columns: usize) -> Vec<Data> {
 
+    let diff = settings.diff.as_ref();
+    let size = |file: &LameFile| diff.map_or(file.size, |d| d.growth(file));
+    let (deleted_count, deleted_size) = diff
+        .map_or((0, 0), |d| d.deleted(tree, view.dir, settings.depth));
+    let total_size = match diff {
+        Some(_) => view.entries.iter()
+            .map(|&e| &tree.entries[e])
+            .filter(|f| !f.deleted)
+            .map(size)
+            .sum::<u64>() + deleted_size,
+        None => tree.entries[view.dir].size
+    };
     let mut data_size: u64 = 0;
     let mut data = Vec::<Data>::new();
-    
-    // create data points for top files
-    for (i, file) in files.iter()
-                        .skip(skip)
-                        .take(NUM_FILES_SHOWN)
-                        .zip(deleted.iter())
+    if total_size == 0 {
+        return data
+    }
+
+    // the legend has a label for every shown entry, the deleted ones and the rest,
+    // each followed by its percentage
+    let shown = view.entries.iter().skip(view.skip).take(settings.slices())
+        .filter(|&&e| !tree.entries[e].deleted)
+        .count();
+    let labels = shown + 1 + (deleted_size > 0) as usize;
+    let width = columns.saturating_sub(chart_width(labels) + " 100.00%".len());
+
+    // create data points for top entries
+    for (i, file) in view.entries.iter()
+                        .skip(view.skip)
+                        .take(settings.slices())
+                        .map(|&e| &tree.entries[e])
                         .enumerate()
-                        .filter(|(_, (_, &d))| !d)      // remove already deleted entries
-                        .map(|(i, (f, _))| (i, f)) {           
+                        .filter(|(_, f)| !f.deleted) {     // remove already deleted entries
+        let mut label = format!("({}) ", i + 1);
+        if let Some(diff) = diff {
+            label.push_str(&format!("{} ", diff.describe(file)));
+        }
+        match settings.relative_paths {
+            true => {
+                let width = width.saturating_sub(label.chars().count());
+                label.push_str(&file.describe_relative(&tree.entries[0].path, width));
+            },
+            false => label.push_str(&file.to_string())
+        }
+        data.push(Data {
+            label,
+            value: size(file) as f32 / total_size as f32,
+            color: Some(slice_color(i)),
+            fill: '•'
+        });
+        data_size += size(file);
+    }
+    if deleted_size > 0 {
         data.push(Data {
-            label: format!("({}) {}", i + 1, file),
-            value: file.size as f32 / total_size as f32,
-            color: Some(Color::Fixed(i as u8 + 1)),
-            fill: '•' 
+            label: format!("Deleted: {} in {} entries", to_readable_size(deleted_size), deleted_count),
+            value: deleted_size as f32 / total_size as f32,
+            color: Some(Color::RGB(200, 60, 60)),
+            fill: 'x'
         });
-        data_size += file.size;
+        data_size += deleted_size;
     }
     // show size of all other files as one datapoint
     let other_size = total_size - data_size;
+    let other = if diff.is_some() { "Other growth" } else { "Other" };
     data.push(Data {
-        label: format!("Other: {}", to_readable_size(other_size)),
+        label: format!("{}: {}", other, to_readable_size(other_size)),
         value: other_size as f32 / total_size as f32,
         color: Some(Color::RGB(100, 100, 100)),
-        fill: '-' 
+        fill: '-'
     } );
 
     data
 }
 
+/// Parses repeatable pattern arguments.
+fn parse_patterns<T>(matches: &ArgMatches, name: &str, parse: fn(&str) -> Result<T, String>)
+        -> Result<Vec<T>, String> {
+    matches.values_of(name).map_or(Ok(Vec::new()), |values| values.map(parse).collect())
+}
+
 /// Parses numeric command line conditions.
 fn parse_time_condition(matches: &ArgMatches, name: &str)
         -> Result<u64, Box<dyn Error>> {
-    let val = matches.value_of(name).unwrap_or("0").parse::<u64>()?;
-    if val > 0 {
-        println!("Only showing files that were {} at least {} days ago.",
-            name, val);
-    }
-    Ok(val)
-}
-
-/// Prompts for user input. The user can delete files, look for more files
-/// and abort the application.
-fn process_input(files: &Vec<LameFile>, skip: &mut usize, total_size: &mut u64,
-        deleted: &mut [bool; NUM_FILES_SHOWN]) -> Result<bool, Box<dyn Error>> {
-
-    let mut choice = String::new();
-    io::stdin().read_line(&mut choice)?;
-    let choice = choice.trim().to_uppercase();
-    
-    match choice.as_str() {
-        "N" => {
-            if *skip + NUM_FILES_SHOWN >= files.len() {
-                println!("\nNo files left, quitting.\n");
-                return Ok(true)
-            }
-            *skip += NUM_FILES_SHOWN;
-            *deleted = [false; NUM_FILES_SHOWN];
-        },
-        "Q" => return Ok(true),
-        _ => {
-            // get file to delete
-            match choice.parse::<usize>() {
-                Ok(n @ 1..=NUM_FILES_SHOWN) => {
-                    let index = n - 1;
-                    if deleted[index] {
-                        eprintln!("Invalid choice\n");
-                    } else {
-                        let file = &files[*skip + index];
-                        // prompt for actual deletion
-                        match confirm_file_deletion(&file.path) {
-                            Ok(done) => {
-                                if done {
-                                    deleted[index] = true;
-                                    *total_size -= file.size;
-                                }
-                            },
-                            Err(_err) => eprintln!("Couldn't delete file\n")
-                        };
-                    }
-                },
-                // wrong number
-                Ok(_) => eprintln!("Invalid choice\n"),
-                Err(_err) => eprintln!("Not a valid number\n")
-            };
-        }
+    Ok(matches.value_of(name).unwrap_or("0").parse::<u64>()?)
+}
+
+/// Prints the entries that would have been deleted without the dry run.
+fn print_dry_run_summary(tree: &Tree) {
+    let selected: Vec<&LameFile> = tree.entries.iter().filter(|e| e.deleted).collect();
+    if selected.is_empty() {
+        println!("Dry run: no files were selected for deletion.");
+        return
+    }
+    println!("Dry run: the following entries would have been deleted:");
+    for file in &selected {
+        let suffix = if file.is_dir { " (dir)" } else { "" };
+        println!("{:>11}  {}{}", to_readable_size(file.size), file.path.display(), suffix);
+    }
+    // sizes of deleted directories don't include entries deleted before
+    let total_size: u64 = selected.iter().map(|e| e.size).sum();
+    println!("\n{} would have been reclaimed.", to_readable_size(total_size));
+}
+
+/// Restores the entry that was deleted most recently, if it was moved to
+/// the trash or only deleted in a dry run. Returns what happened.
+fn undo_deletion(tree: &mut Tree, stack: &mut [View], history: &mut Vec<(usize, Removal)>,
+        settings: &Settings) -> String {
+    let (index, removal) = match history.pop() {
+        Some(deletion) => deletion,
+        None => return String::from("Nothing to undo")
     };
-    Ok(false)
+    let file = &tree.entries[index];
+    if let Removal::Trashed(trashed) = &removal {
+        if let Err(err) = trash::restore(trashed, &file.path) {
+            let message = format!("Couldn't restore {}: {}", file.path.display(), err);
+            history.push((index, removal));
+            return message
+        }
+    }
+    let message = format!("Restored {}", file.path.display());
+    tree.restore(index);
+
+    // show the page with the restored entry
+    let slices = settings.slices();
+    let view = stack.last_mut().unwrap();
+    view.sort(tree, settings);
+    if let Some(position) = view.entries.iter().position(|&e| e == index) {
+        view.skip = position / slices * slices;
+        view.selected = position % slices;
+    }
+    message
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let matches = parse_args();
-    let path = matches.value_of("DIR").unwrap();
+    let app = parse_args();
+    // the diff subcommand takes the same arguments as a normal scan
+    let (matches, compare) = match app.subcommand_matches("diff") {
+        Some(diff) => (diff, true),
+        None => (&app, false)
+    };
 
-    println!("\nSearching for files in {} ...\n", path);
+    let filters = Filters {
+        min_created: parse_time_condition(matches, "created")? * SECONDS_PER_DAY,
+        min_modified: parse_time_condition(matches, "modified")? * SECONDS_PER_DAY,
+        min_accessed: parse_time_condition(matches, "accessed")? * SECONDS_PER_DAY,
+        include: parse_patterns(matches, "include", pattern::Glob::new)?,
+        exclude: parse_patterns(matches, "exclude", pattern::Glob::new)?,
+        regex: parse_patterns(matches, "regex", pattern::Regex::new)?,
+        ignore_files: match (matches.is_present("ignore-files"), matches.is_present("only-ignored")) {
+            (true, _) => IgnoreFiles::Skip,
+            (_, true) => IgnoreFiles::Only,
+            _ => IgnoreFiles::Off
+        },
+        one_file_system: matches.is_present("one-file-system"),
+        follow_symlinks: matches.is_present("follow-symlinks"),
+        min_size: matches.value_of("min-size").map_or(Ok(0), parse_size)?,
+        max_size: matches.value_of("max-size").map(parse_size).transpose()?,
+        skip_fs_types: matches.value_of("skip-fs-types")
+            .map(|types| types.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect())
+            .unwrap_or_default()
+    };
+    let mut settings = Settings {
+        depth: match matches.is_present("dirs") {
+            true => Some(matches.value_of("depth").unwrap_or("1").parse::<usize>()?.max(1)),
+            false => None
+        },
+        trash: trash::SUPPORTED && !matches.is_present("no-trash"),
+        dry_run: matches.is_present("dry-run"),
+        diff: None,
+        slices: match matches.value_of("slices") {
+            Some(slices) => Some(slices.parse::<usize>()?.max(1)),
+            None => None
+        },
+        relative_paths: matches.is_present("relative-paths")
+    };
+    if !trash::SUPPORTED && matches.is_present("trash") {
+        eprintln!("The trash is not supported on this platform, files are deleted permanently.\n");
+    }
+    let threads = match matches.value_of("threads") {
+        Some(threads) => threads.parse::<usize>()?.max(1),
+        None => thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    };
+    // files that grew in place don't change their directory, so diffs and
+    // reports, which are compared or deleted from later, read everything
+    let use_cache = !matches.is_present("no-cache") && !compare && !matches.is_present("format");
+    let disk_usage = matches.is_present("disk-usage");
 
-    let (mut total_size, files) = get_lame_files(path,
-        parse_time_condition(&matches, "created")? * SECONDS_PER_DAY,
-        parse_time_condition(&matches, "modified")? * SECONDS_PER_DAY,
-        parse_time_condition(&matches, "accessed")? * SECONDS_PER_DAY
-    )?;
+    if let Some(source) = matches.value_of("delete-from") {
+        return batch::delete_from(source, &settings)
+    }
+    let path = matches.value_of("DIR").unwrap();
 
-    println!("\nTotal size: {}\n", to_readable_size(total_size));
+    if let Some(format) = matches.value_of("format") {
+        let tree = get_lame_files(path, &filters, threads, use_cache, disk_usage, &progress::Progress::new())?;
+        let mut entries = tree.list(0, settings.depth);
+        if let Some(top) = matches.value_of("top") {
+            entries.truncate(top.parse::<usize>()?);
+        }
+        let stdout = io::stdout();
+        report::write_report(&mut stdout.lock(), format, path, &tree, &entries, &filters)?;
+        return Ok(())
+    }
 
-    let mut skip: usize = 0;
-    let mut deleted = [false; NUM_FILES_SHOWN];
-    let mut quit = false;
+    println!("\nSearching for files in {} ...\n", path);
+    if settings.dry_run {
+        println!("Dry run: no files will be deleted.");
+    }
+    for filter in filters.describe() {
+        println!("Only showing files {}.", filter);
+    }
+    // the earlier scan has to be read before the cache is replaced
+    let snapshot = match compare {
+        true => {
+            println!("Comparing with {}.", matches.value_of("REPORT").unwrap_or("the last scan"));
+            Some(diff::read_snapshot(matches.value_of("REPORT"), path, disk_usage, &filters.describe_walk())?)
+        },
+        false => None
+    };
 
-    while !quit {
-        let data = create_current_data(&files, skip, total_size, &deleted);
-        if total_size <= 0 {
-            println!("No files left, quitting.\n");
-            quit = true;
-        } else {
-            Chart::new()
-                .radius(6)
-                .aspect_ratio(3)
-                .legend(true)
-                .draw(&data);
+    // the largest files can already be deleted while the scan is running
+    let progress = progress::Progress::new();
+    let mut removed = Vec::<(LameFile, Removal)>::new();
+    let (mut tree, quit) = thread::scope(|scope| -> Result<_, Box<dyn Error>> {
+        let scan = scope.spawn(|| get_lame_files(path, &filters, threads, use_cache, disk_usage, &progress)
+            .map_err(|err| err.to_string()));
+        let quit = !matches.is_present("duplicates")
+            && live::run(&progress, || scan.is_finished(), &settings, &mut removed)?;
+        Ok((scan.join().unwrap()?, quit))
+    })?;
+    for error in &tree.errors {
+        println!("{}. Skipping...", error);
+    }
+    let mut history = Vec::<(usize, Removal)>::new();
+    for (file, removal) in removed {
+        if let Some(index) = tree.entries.iter().position(|e| e.path == file.path && !e.deleted) {
+            tree.remove(index);
+            if !matches!(removal, Removal::Deleted) {
+                history.push((index, removal));
+            }
+        }
+    }
+    settings.diff = snapshot.map(|snapshot| diff::Diff::new(snapshot, &tree, path));
+    if quit {
+        if settings.dry_run {
+            print_dry_run_summary(&tree);
+        }
+        return Ok(())
+    }
 
-            println!("\nTop {0} filesizes are shown above. Enter a number to delete, \
-                type n to show the next {0} files or q to quit.", NUM_FILES_SHOWN);
-            print!("Input: ");
-            io::stdout().flush()?;
+    match tree.entries[0].apparent_size {
+        Some(apparent_size) => println!("\nTotal size: {} on disk, {} apparent\n",
+            to_readable_size(tree.entries[0].size), to_readable_size(apparent_size)),
+        None => println!("\nTotal size: {}\n", to_readable_size(tree.entries[0].size))
+    }
 
-            quit = process_input(&files, &mut skip, &mut total_size, &mut deleted)?;
+    if matches.is_present("duplicates") {
+        duplicates::run(&mut tree, &settings)?;
+        if settings.dry_run {
+            print_dry_run_summary(&tree);
         }
+        return Ok(())
+    }
+
+    if let Some(farewell) = tui::run(&mut tree, &mut history, &settings)? {
+        println!("{}\n", farewell);
+    }
+    if settings.dry_run {
+        print_dry_run_summary(&tree);
     }
     Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file hard linked as `a/big` and `c/link`, whose data is counted for `a/big`.
+    fn linked_tree() -> (Tree, [usize; 5]) {
+        let mut tree = Tree::new();
+        let root = tree.push(None, "root", 0, true);
+        let a = tree.push(Some(root), "a", 0, true);
+        let big = tree.push(Some(a), "big", 1000, false);
+        let c = tree.push(Some(root), "c", 0, true);
+        let link = tree.push(Some(c), "link", 0, false);
+        tree.shared.push(Shared { size: 1000, apparent_size: None, links: vec![big, link], counted: big });
+        (tree, [root, a, big, c, link])
+    }
+
+    fn sizes(tree: &Tree) -> Vec<u64> {
+        tree.entries.iter().map(|e| e.size).collect()
+    }
+
+    #[test]
+    fn remove_hard_links() {
+        let (mut tree, [_, _, big, _, link]) = linked_tree();
+        tree.remove(big);
+        assert_eq!(sizes(&tree), [1000, 0, 0, 1000, 1000]);
+        tree.remove(link);
+        assert_eq!(sizes(&tree)[0], 0);
+
+        tree.restore(link);
+        assert_eq!(sizes(&tree), [1000, 0, 0, 1000, 1000]);
+        tree.restore(big);
+        assert_eq!(sizes(&tree), [1000, 0, 0, 1000, 1000]);
+    }
+
+    #[test]
+    fn remove_dirs_with_hard_links() {
+        let (mut tree, [_, a, _, c, _]) = linked_tree();
+        tree.remove(a);
+        assert_eq!(sizes(&tree), [1000, 0, 0, 1000, 1000]);
+        tree.remove(c);
+        assert_eq!(sizes(&tree)[0], 0);
+
+        // the data is counted again for the first restored link
+        tree.restore(a);
+        assert_eq!(sizes(&tree), [1000, 1000, 1000, 0, 0]);
+        tree.restore(c);
+        assert_eq!(sizes(&tree), [1000, 1000, 1000, 0, 0]);
+    }
+
+    #[test]
+    fn remove_unlinked() {
+        let (mut tree, [root, a, big, c, link]) = linked_tree();
+        let small = tree.push(Some(c), "small", 10, false);
+        tree.remove(small);
+        assert_eq!(sizes(&tree), [1000, 1000, 1000, 0, 0, 10]);
+        assert!(tree.is_removed(small) && !tree.is_removed(link));
+        tree.restore(small);
+        assert_eq!(tree.entries[c].size, 10);
+        assert_eq!(tree.entries[root].size, 1010);
+        assert!(tree.is_below(big, a) && !tree.is_below(big, c));
+    }
+
+    #[test]
+    fn sizes_without_unit() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size(" 512 "), Ok(512));
+        assert_eq!(parse_size("512b"), Ok(512));
+        assert_eq!(parse_size("1 Byte"), Ok(1));
+        assert_eq!(parse_size("512 bytes"), Ok(512));
+    }
+
+    #[test]
+    fn binary_units() {
+        assert_eq!(parse_size("4k"), Ok(4096));
+        assert_eq!(parse_size("4KiB"), Ok(4096));
+        assert_eq!(parse_size("100M"), Ok(100 << 20));
+        assert_eq!(parse_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_size("2T"), Ok(2 << 40));
+        assert_eq!(parse_size("3PiB"), Ok(3 << 50));
+        assert_eq!(parse_size("1E"), Ok(1 << 60));
+    }
+
+    #[test]
+    fn decimal_units() {
+        assert_eq!(parse_size("500kB"), Ok(500_000));
+        assert_eq!(parse_size("500 KB"), Ok(500_000));
+        assert_eq!(parse_size("1.5MB"), Ok(1_500_000));
+        assert_eq!(parse_size("3gb"), Ok(3_000_000_000));
+        assert_eq!(parse_size("1TB"), Ok(1_000_000_000_000));
+        assert_eq!(parse_size("2PB"), Ok(2_000_000_000_000_000));
+    }
+
+    #[test]
+    fn readable_sizes() {
+        assert_eq!(to_readable_size(0), "0.00 Byte");
+        assert_eq!(to_readable_size(1), "1.00 Byte");
+        assert_eq!(to_readable_size(512), "512.00 Byte");
+        assert_eq!(to_readable_size(1536), "1.50 KiB");
+        assert_eq!(to_readable_size(3 << 40), "3.00 TiB");
+        assert_eq!(to_readable_size(parse_size("2000T").unwrap()), "1.95 PiB");
+        assert_eq!(to_readable_size(u64::MAX), "16.00 EiB");
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("1.2.3k").is_err());
+        assert!(parse_size("-5k").is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,66 @@
+//! Mounted filesystems, so filesystems of certain types like `proc` or `nfs`
+//! can be left out of the scan.
+
+use std::{path, collections::HashSet};
+
+/// Table of mounted filesystems, one per line, e.g.
+/// `proc /proc proc rw,nosuid 0 0`.
+#[cfg(target_os = "linux")]
+const MOUNTS: &str = "/proc/self/mounts";
+
+/// Decodes the octal escapes of spaces, tabs, newlines and backslashes in a
+/// mount point, e.g. `\040`.
+#[cfg(target_os = "linux")]
+fn unescape(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let code = tail.get(..3)
+            .filter(|_| byte == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) => {
+                bytes.push(code);
+                rest = &tail[3..];
+            },
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Reads the mount points of all filesystems of the given types.
+#[cfg(target_os = "linux")]
+pub fn mount_points(types: &[String]) -> HashSet<path::PathBuf> {
+    if types.is_empty() {
+        return HashSet::new()
+    }
+    let mounts = match std::fs::read_to_string(MOUNTS) {
+        Ok(mounts) => mounts,
+        Err(err) => {
+            eprintln!("Couldn't read the mounted filesystems: {}\n", err);
+            return HashSet::new()
+        }
+    };
+    mounts.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ').skip(1);
+            Some((fields.next()?, fields.next()?))
+        })
+        .filter(|(_, kind)| types.iter().any(|t| t == kind))
+        .map(|(mount_point, _)| path::PathBuf::from(unescape(mount_point)))
+        .collect()
+}
+
+/// There is no table of mounted filesystems to read.
+#[cfg(not(target_os = "linux"))]
+pub fn mount_points(types: &[String]) -> HashSet<path::PathBuf> {
+    if !types.is_empty() {
+        eprintln!("Filesystem types can only be skipped on Linux.\n");
+    }
+    HashSet::new()
+}
@@ -0,0 +1,568 @@
+//! Glob patterns and regular expressions to filter the scanned paths.
+//!
+//! Globs are translated into regular expressions, which are compiled into a
+//! small NFA. Matching simulates all states at once, so it takes linear time
+//! no matter how the expression looks.
+
+use std::{fmt, path};
+
+/// Parsed regular expression.
+enum Node {
+    Char(char),
+    Any,
+    /// Character ranges, negated if the flag is set.
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+/// Instruction of the compiled NFA.
+#[derive(Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// A regular expression supporting the common syntax: `.`, `[a-z]`, `[^a-z]`,
+/// `\d`, `\w`, `\s`, `^`, `$`, groups, `|` and the quantifiers `*`, `+`, `?`
+/// and `{n,m}`. Matches anywhere in the text unless anchored.
+pub struct Regex {
+    source: String,
+    program: Vec<Inst>,
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    source: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("invalid regex {:?} at position {}: {}", self.source, self.pos, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn alternate(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concat()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap(),
+            _ => Node::Alternate(branches)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let start = self.pos;
+                self.pos += 1;
+                let min = self.number();
+                let max = match self.peek() {
+                    Some(',') => {
+                        self.pos += 1;
+                        self.number()
+                    },
+                    _ => min
+                };
+                match (min, self.peek()) {
+                    (Some(min), Some('}')) if max.is_none_or(|max| min <= max) => (min, max),
+                    // not a valid repetition, treat the brace literally
+                    _ => {
+                        self.pos = start;
+                        return Ok(atom)
+                    }
+                }
+            },
+            _ => return Ok(atom)
+        };
+        self.pos += 1;
+        // lazy quantifiers match the same texts
+        if self.peek() == Some('?') {
+            self.pos += 1;
+        }
+        if matches!(self.peek(), Some('*') | Some('+')) {
+            return Err(self.error("nested quantifier"))
+        }
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.chars.get(self.pos..self.pos + 2) == Some(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let node = self.alternate()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("missing )"))
+                }
+                self.pos += 1;
+                node
+            },
+            '[' => self.class()?,
+            '\\' => self.escape()?,
+            '*' | '+' | '?' => return Err(self.error("quantifier without anything to repeat")),
+            c => Node::Char(c)
+        })
+    }
+
+    /// Returns the ranges of the class escapes `\d`, `\w` and `\s`.
+    fn class_escape(c: char) -> Option<(Vec<(char, char)>, bool)> {
+        let digits = vec![('0', '9')];
+        let word = vec![('0', '9'), ('A', 'Z'), ('a', 'z'), ('_', '_')];
+        let space = vec![(' ', ' '), ('\t', '\r')];
+        match c {
+            'd' => Some((digits, false)),
+            'D' => Some((digits, true)),
+            'w' => Some((word, false)),
+            'W' => Some((word, true)),
+            's' => Some((space, false)),
+            'S' => Some((space, true)),
+            _ => None
+        }
+    }
+
+    fn escaped_char(c: char) -> char {
+        match c {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            c => c
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let c = self.peek().ok_or_else(|| self.error("trailing backslash"))?;
+        self.pos += 1;
+        Ok(match Parser::class_escape(c) {
+            Some((ranges, negated)) => Node::Class(ranges, negated),
+            None => Node::Char(Parser::escaped_char(c))
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("missing ]"))?;
+            self.pos += 1;
+            // a ] right at the start is a literal
+            if c == ']' && !first {
+                break
+            }
+            first = false;
+            let start = match c {
+                '\\' => {
+                    let e = self.peek().ok_or_else(|| self.error("missing ]"))?;
+                    self.pos += 1;
+                    if let Some((mut class, false)) = Parser::class_escape(e) {
+                        ranges.append(&mut class);
+                        continue
+                    }
+                    Parser::escaped_char(e)
+                },
+                c => c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&e| e != ']') {
+                self.pos += 1;
+                let mut end = self.peek().unwrap();
+                self.pos += 1;
+                if end == '\\' {
+                    end = Parser::escaped_char(self.peek().ok_or_else(|| self.error("missing ]"))?);
+                    self.pos += 1;
+                }
+                if end < start {
+                    return Err(self.error("invalid range"))
+                }
+                ranges.push((start, end));
+            } else {
+                ranges.push((start, start));
+            }
+        }
+        Ok(Node::Class(ranges, negated))
+    }
+}
+
+/// Appends the instructions for a node to the program.
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(ranges, negated) => program.push(Inst::Class(ranges.clone(), *negated)),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => nodes.iter().for_each(|n| compile(n, program)),
+        Node::Alternate(branches) => {
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program);
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                } else {
+                    compile(branch, program);
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        },
+        Node::Repeat(node, min, max) => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                },
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(source: &str) -> Result<Regex, String> {
+        let mut parser = Parser { chars: source.chars().collect(), pos: 0, source };
+        let node = parser.alternate()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched )"))
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex { source: source.to_string(), program })
+    }
+
+    /// Adds a state and all states reachable from it without consuming a character.
+    fn add_state(&self, states: &mut Vec<usize>, seen: &mut [bool], pc: usize, at_start: bool,
+            at_end: bool) {
+        if seen[pc] {
+            return
+        }
+        seen[pc] = true;
+        match self.program[pc] {
+            Inst::Jump(target) => self.add_state(states, seen, target, at_start, at_end),
+            Inst::Split(a, b) => {
+                self.add_state(states, seen, a, at_start, at_end);
+                self.add_state(states, seen, b, at_start, at_end);
+            },
+            Inst::Start if at_start => self.add_state(states, seen, pc + 1, at_start, at_end),
+            Inst::End if at_end => self.add_state(states, seen, pc + 1, at_start, at_end),
+            Inst::Start | Inst::End => (),
+            _ => states.push(pc)
+        }
+    }
+
+    /// Checks if the expression matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut states = Vec::new();
+        for pos in 0..=chars.len() {
+            // a match may start at every position
+            let mut seen = vec![false; self.program.len()];
+            for &pc in &states {
+                seen[pc] = true;
+            }
+            self.add_state(&mut states, &mut seen, 0, pos == 0, pos == chars.len());
+            if states.iter().any(|&pc| matches!(self.program[pc], Inst::Match)) {
+                return true
+            }
+            if pos == chars.len() {
+                break
+            }
+
+            let c = chars[pos];
+            let mut next = Vec::new();
+            let mut seen = vec![false; self.program.len()];
+            for &pc in &states {
+                let matched = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class(ranges, negated) =>
+                        ranges.iter().any(|&(a, b)| a <= c && c <= b) != *negated,
+                    _ => false
+                };
+                if matched {
+                    self.add_state(&mut next, &mut seen, pc + 1, false, pos + 1 == chars.len());
+                }
+            }
+            states = next;
+        }
+        false
+    }
+}
+
+/// A glob pattern like `*.log`, `target` or `src/**/*.rs`. Patterns without a
+/// slash match file names, others match paths relative to the scanned directory.
+pub struct Glob {
+    source: String,
+    regex: Regex,
+    match_path: bool,
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Escapes characters that have a special meaning in regular expressions.
+fn escape_regex(c: char, regex: &mut String) {
+    if "\\.+*?()|[]{}^$".contains(c) {
+        regex.push('\\');
+    }
+    regex.push(c);
+}
+
+impl Glob {
+    pub fn new(source: &str) -> Result<Glob, String> {
+        let trimmed = source.trim_end_matches('/');
+        let match_path = trimmed.contains('/');
+        let pattern = trimmed.trim_start_matches('/');
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut regex = String::from("^");
+        let mut in_braces = false;
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    i += 1;
+                    if chars.get(i + 1) == Some(&'/') {
+                        // "**/" also matches no directory at all
+                        i += 1;
+                        regex.push_str("(.*/)?");
+                    } else {
+                        regex.push_str(".*");
+                    }
+                },
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                '[' => {
+                    let end = chars[i + 1..].iter().skip(1).position(|&c| c == ']')
+                        .map(|p| i + p + 2)
+                        .ok_or_else(|| format!("invalid glob {:?}: missing ]", source))?;
+                    regex.push('[');
+                    let mut class: String = chars[i + 1..end].iter().collect();
+                    if class.starts_with('!') {
+                        class.replace_range(..1, "^");
+                    }
+                    regex.push_str(&class.replace('\\', "\\\\"));
+                    regex.push(']');
+                    i = end;
+                },
+                '{' if !in_braces => {
+                    in_braces = true;
+                    regex.push('(');
+                },
+                ',' if in_braces => regex.push('|'),
+                '}' if in_braces => {
+                    in_braces = false;
+                    regex.push(')');
+                },
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    escape_regex(chars[i], &mut regex);
+                },
+                c => escape_regex(c, &mut regex)
+            }
+            i += 1;
+        }
+        if in_braces {
+            return Err(format!("invalid glob {:?}: missing }}", source))
+        }
+        regex.push('$');
+        Ok(Glob {
+            source: source.to_string(),
+            regex: Regex::new(&regex).map_err(|_| format!("invalid glob {:?}", source))?,
+            match_path
+        })
+    }
+
+    /// Checks if the glob matches a path relative to the scanned directory.
+    pub fn is_match(&self, relative: &path::Path) -> bool {
+        if self.match_path {
+            let components: Vec<_> = relative.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            self.regex.is_match(&components.join("/"))
+        } else {
+            relative.file_name()
+                .is_some_and(|name| self.regex.is_match(&name.to_string_lossy()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(source: &str, path: &str) -> bool {
+        Glob::new(source).unwrap().is_match(path::Path::new(path))
+    }
+
+    fn regex(source: &str, text: &str) -> bool {
+        Regex::new(source).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn glob_names() {
+        assert!(glob("*.log", "var/log/syslog.log"));
+        assert!(!glob("*.log", "var/log/syslog.log.1"));
+        assert!(glob("file?.txt", "file1.txt"));
+        assert!(!glob("file?.txt", "file10.txt"));
+        assert!(glob("target/", "deep/target"));
+    }
+
+    #[test]
+    fn glob_double_star() {
+        assert!(glob("src/**/*.rs", "src/main.rs"));
+        assert!(glob("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(!glob("src/**/*.rs", "test/src/main.rs"));
+        assert!(glob("**/target", "target"));
+        assert!(glob("**/target", "a/b/target"));
+        assert!(glob("a/**", "a/b/c"));
+        assert!(!glob("src/*.rs", "src/a/main.rs"));
+    }
+
+    #[test]
+    fn glob_classes() {
+        assert!(glob("[abc].txt", "b.txt"));
+        assert!(!glob("[abc].txt", "d.txt"));
+        assert!(glob("[!abc].txt", "d.txt"));
+        assert!(!glob("[!abc].txt", "a.txt"));
+        assert!(glob("[0-9]*", "2024-report"));
+        assert!(glob("[]]", "]"));
+        assert!(Glob::new("[abc").is_err());
+    }
+
+    #[test]
+    fn glob_braces() {
+        assert!(glob("*.{jpg,png}", "photo.jpg"));
+        assert!(glob("*.{jpg,png}", "photo.png"));
+        assert!(!glob("*.{jpg,png}", "photo.gif"));
+        assert!(glob("{a,b}.txt", "a.txt"));
+        assert!(Glob::new("*.{jpg,png").is_err());
+    }
+
+    #[test]
+    fn glob_escapes() {
+        assert!(glob("a+b(1).txt", "a+b(1).txt"));
+        assert!(!glob("a.txt", "abtxt"));
+        assert!(glob("\\*.txt", "*.txt"));
+        assert!(!glob("\\*.txt", "a.txt"));
+    }
+
+    #[test]
+    fn regex_repeats() {
+        assert!(regex("^a{2,3}$", "aa"));
+        assert!(regex("^a{2,3}$", "aaa"));
+        assert!(!regex("^a{2,3}$", "a"));
+        assert!(!regex("^a{2,3}$", "aaaa"));
+        assert!(regex("^a{2}$", "aa"));
+        assert!(!regex("^a{2}$", "aaa"));
+        assert!(regex("^a{2,}$", "aaaaa"));
+        assert!(regex("^(ab)+$", "ababab"));
+        assert!(regex("^x?y*z+$", "zz"));
+    }
+
+    #[test]
+    fn regex_anchors() {
+        assert!(regex("log", "syslog.1"));
+        assert!(!regex("^log", "syslog"));
+        assert!(regex("^log", "log.1"));
+        assert!(regex("\\.log$", "sys.log"));
+        assert!(!regex("\\.log$", "sys.log.1"));
+        assert!(regex("^$", ""));
+        assert!(!regex("^$", "a"));
+    }
+
+    #[test]
+    fn regex_classes() {
+        assert!(regex("^\\d+\\.\\w+$", "2024.tar_gz"));
+        assert!(!regex("^\\d+$", "12a"));
+        assert!(regex("^[^a-c]+$", "xyz"));
+        assert!(!regex("^[^a-c]+$", "xbz"));
+        assert!(regex("^(foo|bar)\\s", "bar baz"));
+    }
+
+    #[test]
+    fn regex_errors() {
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("[a").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! A progress line on stderr while scanning. It's only shown if stderr is a
+//! terminal, so it doesn't end up in redirected output. The largest files
+//! found so far are kept as well, for a preview while the scan is running.
+
+use std::{io, path, time, io::IsTerminal, sync::Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{LameFile, to_readable_size};
+
+/// Milliseconds between updates of the line.
+const INTERVAL: u64 = 100;
+/// Directories are shortened to this many characters.
+const MAX_DIR_LENGTH: usize = 40;
+/// Number of largest files kept for the preview.
+const LARGEST_KEPT: usize = 100;
+
+pub struct Progress {
+    enabled: bool,
+    /// Set while something else is shown, e.g. the preview.
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+    start: time::Instant,
+    files: AtomicU64,
+    bytes: AtomicU64,
+    /// Milliseconds since the start at which the line was last drawn.
+    drawn: AtomicU64,
+    /// Largest files found so far, highest size on top.
+    largest: Mutex<Vec<LameFile>>,
+    /// Size a file needs to exceed to be kept in `largest`.
+    threshold: AtomicU64,
+    /// Counts the changes of `largest`.
+    changes: AtomicU64,
+}
+
+/// Keeps the end of long paths, which tells more about the directory.
+fn shorten(dir: &path::Path) -> String {
+    let dir = dir.to_string_lossy();
+    let length = dir.chars().count();
+    if length <= MAX_DIR_LENGTH {
+        return dir.to_string()
+    }
+    let end: String = dir.chars().skip(length - MAX_DIR_LENGTH + 3).collect();
+    format!("...{}", end)
+}
+
+impl Progress {
+    pub fn new() -> Progress {
+        Progress {
+            enabled: io::stderr().is_terminal(),
+            paused: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            start: time::Instant::now(),
+            files: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            drawn: AtomicU64::new(0),
+            largest: Mutex::new(Vec::new()),
+            threshold: AtomicU64::new(0),
+            changes: AtomicU64::new(0)
+        }
+    }
+
+    /// Counts a visited entry and updates the line now and then. Can be
+    /// called from several threads.
+    pub fn visit(&self, path: &path::Path, size: u64) {
+        let files = self.files.fetch_add(1, Ordering::Relaxed) + 1;
+        let bytes = self.bytes.fetch_add(size, Ordering::Relaxed) + size;
+        if !self.enabled || self.paused.load(Ordering::Relaxed) {
+            return
+        }
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        let drawn = self.drawn.load(Ordering::Relaxed);
+        // only one thread gets to draw the line
+        if elapsed >= drawn + INTERVAL && self.drawn
+                .compare_exchange(drawn, elapsed, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            eprint!("\r\x1b[K{} files, {}, {:.1}s: {}", files, to_readable_size(bytes),
+                elapsed as f64 / 1000.0, shorten(path.parent().unwrap_or(path)));
+        }
+    }
+
+    /// Keeps a matching file if it's one of the largest found so far.
+    pub fn found(&self, file: &LameFile) {
+        if file.size <= self.threshold.load(Ordering::Relaxed) {
+            return
+        }
+        let mut largest = self.largest.lock().unwrap();
+        let position = largest.partition_point(|f| f.size >= file.size);
+        largest.insert(position, file.clone());
+        if largest.len() > LARGEST_KEPT {
+            largest.pop();
+            self.threshold.store(largest.last().unwrap().size, Ordering::Relaxed);
+        }
+        self.changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Removes a file from the largest ones, e.g. after it was deleted.
+    pub fn forget(&self, path: &path::Path) {
+        self.largest.lock().unwrap().retain(|f| f.path != path);
+        self.changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn largest(&self, count: usize) -> Vec<LameFile> {
+        self.largest.lock().unwrap().iter().take(count).cloned().collect()
+    }
+
+    pub fn changes(&self) -> u64 {
+        self.changes.load(Ordering::Relaxed)
+    }
+
+    /// Number of entries and bytes visited so far.
+    pub fn totals(&self) -> (u64, u64) {
+        (self.files.load(Ordering::Relaxed), self.bytes.load(Ordering::Relaxed))
+    }
+
+    /// Stops drawing the line and removes it.
+    pub fn pause(&self) {
+        if !self.paused.swap(true, Ordering::Relaxed) && self.enabled
+                && self.drawn.load(Ordering::Relaxed) > 0 {
+            eprint!("\r\x1b[K");
+        }
+    }
+
+    /// Draws the line again after `pause`.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Asks the scan to stop early.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Removes the line once the scan is done.
+    pub fn finish(&self) {
+        if self.enabled && self.drawn.load(Ordering::Relaxed) > 0 && !self.paused.load(Ordering::Relaxed) {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
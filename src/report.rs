@@ -0,0 +1,118 @@
+//! Non-interactive reports of the scan results, e.g. for cron jobs.
+
+use std::{io, time};
+
+use crate::{Tree, LameFile, Filters, json, to_readable_size};
+
+/// Writes the given entries of the tree in one of the formats json, csv or text.
+pub fn write_report(out: &mut impl io::Write, format: &str, root: &str, tree: &Tree,
+        entries: &[usize], filters: &Filters) -> io::Result<()> {
+    if format == "json" {
+        return write_json(out, root, tree, entries, filters)
+    }
+    let entries: Vec<&LameFile> = entries.iter().map(|&e| &tree.entries[e]).collect();
+    match format {
+        "csv" => write_csv(out, root, tree, &entries, filters),
+        _ => write_text(out, root, tree, &entries, filters)
+    }
+}
+
+/// Seconds since the unix epoch of the last modification, if known.
+fn modified_secs(file: &LameFile) -> Option<u64> {
+    file.modified
+        .and_then(|m| m.duration_since(time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+fn kind(file: &LameFile) -> &'static str {
+    match (file.is_dir, file.is_symlink) {
+        (true, _) => "dir",
+        (_, true) => "symlink",
+        _ => "file"
+    }
+}
+
+/// Hard links whose data is counted for another link are written with their
+/// real size and `"counted": false`, so batch deletion can check their size.
+fn write_json(out: &mut impl io::Write, root: &str, tree: &Tree, entries: &[usize],
+        filters: &Filters) -> io::Result<()> {
+    let filters: Vec<String> = filters.describe().iter().map(|f| json::quote(f)).collect();
+    let errors: Vec<String> = tree.errors.iter().map(|e| json::quote(e)).collect();
+    let entries: Vec<String> = entries.iter().map(|&index| {
+        let file = &tree.entries[index];
+        let modified = match modified_secs(file) {
+            Some(secs) => secs.to_string(),
+            None => String::from("null")
+        };
+        let (size, apparent_size, counted) = match file.shared.map(|s| &tree.shared[s]) {
+            Some(shared) if shared.counted != index => (shared.size, shared.apparent_size, ", \"counted\": false"),
+            _ => (file.size, file.apparent_size, "")
+        };
+        // with --disk-usage the size is the space on disk, batch deletion
+        // checks the apparent size
+        let apparent_size = match apparent_size {
+            Some(size) => format!(", \"apparent_size\": {}", size),
+            None => String::new()
+        };
+        format!("{{\"path\": {}, \"type\": \"{}\", \"size\": {}{}{}, \"modified\": {}}}",
+            json::quote(&file.path.to_string_lossy()), kind(file), size, apparent_size, counted, modified)
+    }).collect();
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"root\": {},", json::quote(root))?;
+    writeln!(out, "  \"total_size\": {},", tree.entries[0].size)?;
+    writeln!(out, "  \"filters\": {},", json::array(&filters, "  "))?;
+    writeln!(out, "  \"errors\": {},", json::array(&errors, "  "))?;
+    writeln!(out, "  \"entries\": {}", json::array(&entries, "  "))?;
+    writeln!(out, "}}")
+}
+
+/// Quotes a CSV field if necessary.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes the entries as CSV. Everything else is written as comment lines
+/// starting with `#` before the header.
+fn write_csv(out: &mut impl io::Write, root: &str, tree: &Tree, entries: &[&LameFile],
+        filters: &Filters) -> io::Result<()> {
+    writeln!(out, "# root: {}", root)?;
+    writeln!(out, "# total_size: {}", tree.entries[0].size)?;
+    for filter in filters.describe() {
+        writeln!(out, "# filter: {}", filter)?;
+    }
+    for error in &tree.errors {
+        writeln!(out, "# error: {}", error.replace('\n', " "))?;
+    }
+    writeln!(out, "path,type,size,modified")?;
+    for file in entries {
+        let modified = modified_secs(file).map(|s| s.to_string()).unwrap_or_default();
+        writeln!(out, "{},{},{},{}", csv_field(&file.path.to_string_lossy()), kind(file),
+            file.size, modified)?;
+    }
+    Ok(())
+}
+
+fn write_text(out: &mut impl io::Write, root: &str, tree: &Tree, entries: &[&LameFile],
+        filters: &Filters) -> io::Result<()> {
+    writeln!(out, "Total size of {}: {}", root, to_readable_size(tree.entries[0].size))?;
+    for filter in filters.describe() {
+        writeln!(out, "Only showing files {}.", filter)?;
+    }
+    writeln!(out)?;
+    for file in entries {
+        let suffix = if file.is_dir { " (dir)" } else { "" };
+        writeln!(out, "{:>11}  {}{}", to_readable_size(file.size), file.path.display(), suffix)?;
+    }
+    if !tree.errors.is_empty() {
+        writeln!(out, "\nErrors:")?;
+        for error in &tree.errors {
+            writeln!(out, "  {}", error)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+//! Moves files to the trash as described by the freedesktop.org Trash
+//! specification, so they can be restored with any file manager.
+
+use std::{fs, io, path};
+
+/// Location of a file that was moved to the trash.
+pub struct Trashed {
+    /// Where the file is located inside the trash.
+    pub file: path::PathBuf,
+    /// The `.trashinfo` file describing the trashed file.
+    pub info: path::PathBuf,
+}
+
+/// Moves a file or directory into the trash of the filesystem it is located on.
+/// Files in the home filesystem go to `$XDG_DATA_HOME/Trash`, files on other
+/// mounts to `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid`.
+#[cfg(unix)]
+pub fn move_to_trash(path: &path::Path) -> io::Result<Trashed> {
+    use std::os::unix::fs::MetadataExt;
+
+    let original = absolute(path)?;
+    let dev = fs::symlink_metadata(&original)?.dev();
+
+    let home_trash = home_trash_dir()?;
+    fs::create_dir_all(&home_trash)?;
+    if fs::metadata(&home_trash)?.dev() == dev {
+        return trash_into(&original, &home_trash, None)
+    }
+
+    let topdir = mount_point(&original, dev)?;
+    let uid = unsafe { libc::getuid() };
+    let shared = topdir.join(".Trash");
+    if is_shared_trash(&shared) {
+        let trash = shared.join(uid.to_string());
+        if create_private_dir(&trash).is_ok() {
+            return trash_into(&original, &trash, Some(&topdir))
+        }
+    }
+    let trash = topdir.join(format!(".Trash-{}", uid));
+    create_private_dir(&trash)?;
+    trash_into(&original, &trash, Some(&topdir))
+}
+
+/// Whether files can be moved to the trash on this platform.
+pub const SUPPORTED: bool = cfg!(unix);
+
+#[cfg(not(unix))]
+pub fn move_to_trash(_path: &path::Path) -> io::Result<Trashed> {
+    Err(io::Error::new(io::ErrorKind::Other, "the trash is not supported on this platform"))
+}
+
+/// Moves a trashed file back to its original location. Fails if another
+/// file has been created at that location in the meantime.
+pub fn restore(trashed: &Trashed, original: &path::Path) -> io::Result<()> {
+    if fs::symlink_metadata(original).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+            format!("{} already exists", original.display())))
+    }
+    fs::rename(&trashed.file, original)?;
+    fs::remove_file(&trashed.info)
+}
+
+/// Makes a path absolute without resolving a symlink at its end, as the link
+/// itself has to be trashed and not its target.
+#[cfg(unix)]
+fn absolute(path: &path::Path) -> io::Result<path::PathBuf> {
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let parent = match path.parent() {
+        Some(parent) if parent != path::Path::new("") => parent,
+        _ => path::Path::new(".")
+    };
+    Ok(fs::canonicalize(parent)?.join(name))
+}
+
+/// Returns `$XDG_DATA_HOME/Trash`, falling back to `~/.local/share/Trash`.
+#[cfg(unix)]
+fn home_trash_dir() -> io::Result<path::PathBuf> {
+    use std::env;
+    if let Some(data_home) = env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        return Ok(path::PathBuf::from(data_home).join("Trash"))
+    }
+    env::var_os("HOME")
+        .map(|home| path::PathBuf::from(home).join(".local/share/Trash"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))
+}
+
+/// Finds the top directory of the mount a path with the given device ID is located on.
+#[cfg(unix)]
+fn mount_point(path: &path::Path, dev: u64) -> io::Result<path::PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut topdir = path.parent().unwrap_or(path);
+    while let Some(parent) = topdir.parent() {
+        if fs::metadata(parent)?.dev() != dev {
+            break
+        }
+        topdir = parent;
+    }
+    Ok(topdir.to_path_buf())
+}
+
+/// An administrator-created `$topdir/.Trash` may only be used if it is a real
+/// directory with the sticky bit set.
+#[cfg(unix)]
+fn is_shared_trash(path: &path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.is_dir() && metadata.permissions().mode() & 0o1000 != 0,
+        Err(_) => false
+    }
+}
+
+/// Creates a directory that is only accessible by the current user.
+#[cfg(unix)]
+fn create_private_dir(path: &path::Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    if path.is_dir() {
+        return Ok(())
+    }
+    fs::DirBuilder::new().mode(0o700).create(path)
+}
+
+/// Moves a file into the given trash directory. The `.trashinfo` file is
+/// created first, as it reserves the name inside the trash. Paths of files
+/// in a trash at the top of a mount are stored relative to `topdir`.
+#[cfg(unix)]
+fn trash_into(original: &path::Path, trash: &path::Path, topdir: Option<&path::Path>)
+        -> io::Result<Trashed> {
+    use std::{io::Write, time};
+    let files = trash.join("files");
+    let infos = trash.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&infos)?;
+
+    let stored_path = match topdir {
+        Some(topdir) => original.strip_prefix(topdir).unwrap_or(original),
+        None => original
+    };
+    let name = original.file_name().unwrap().to_string_lossy();
+
+    for n in 1.. {
+        let candidate = match n {
+            1 => name.to_string(),
+            _ => format!("{}.{}", name, n)
+        };
+        let file = files.join(&candidate);
+        let info = infos.join(format!("{}.trashinfo", candidate));
+        if fs::symlink_metadata(&file).is_ok() {
+            continue
+        }
+        let mut info_file = match fs::OpenOptions::new().write(true).create_new(true).open(&info) {
+            Ok(info_file) => info_file,
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err)
+        };
+        let written = write!(info_file, "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            crate::cache::encode(stored_path.as_os_str()),
+            crate::info::local_time(time::SystemTime::now(), 'T').unwrap_or_default());
+        if let Err(err) = written.and_then(|_| fs::rename(original, &file)) {
+            fs::remove_file(&info).ok();
+            return Err(err)
+        }
+        return Ok(Trashed { file, info })
+    }
+    unreachable!()
+}
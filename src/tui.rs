@@ -0,0 +1,684 @@
+//! Full screen interface on the alternate screen of the terminal. Entries are
+//! selected with the arrow keys and deleted, zoomed into or paged through
+//! with single keys. The screen is drawn again when the terminal is resized.
+//! The live preview and the duplicates use the same terminal.
+
+use std::{io, io::Write, time, error::Error, collections::VecDeque};
+
+use crate::info;
+use crate::{Tree, View, Settings, Removal, Confirmation,
+    to_readable_size, create_current_data, remove_confirmed, undo_deletion, draw_chart, chart_width};
+
+/// Columns left for the legend when the number of slices follows the terminal.
+const LEGEND_WIDTH: usize = 50;
+
+/// How often the terminal size is checked while waiting for keys.
+pub const POLL: time::Duration = time::Duration::from_millis(200);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Delete,
+    Enter,
+    Backspace,
+    Escape,
+    /// Ctrl-C, which doesn't stop the process while the terminal is set up.
+    Interrupt,
+    Char(char),
+}
+
+/// Splits what was read from the terminal into keys.
+fn parse_keys(bytes: &[u8], keys: &mut VecDeque<Key>) {
+    let mut i = 0;
+    while i < bytes.len() {
+        let (key, length) = match &bytes[i..] {
+            [0x1b, b'[' | b'O', b'A', ..] => (Key::Up, 3),
+            [0x1b, b'[' | b'O', b'B', ..] => (Key::Down, 3),
+            [0x1b, b'[' | b'O', b'C', ..] => (Key::Right, 3),
+            [0x1b, b'[' | b'O', b'D', ..] => (Key::Left, 3),
+            [0x1b, b'[', b'3', b'~', ..] => (Key::Delete, 4),
+            [0x1b, b'[', b'5', b'~', ..] => (Key::PageUp, 4),
+            [0x1b, b'[', b'6', b'~', ..] => (Key::PageDown, 4),
+            [0x1b, ..] => (Key::Escape, 1),
+            [b'\r' | b'\n', ..] => (Key::Enter, 1),
+            [0x7f | 0x08, ..] => (Key::Backspace, 1),
+            [0x03, ..] => (Key::Interrupt, 1),
+            rest => {
+                let valid = match std::str::from_utf8(&rest[..rest.len().min(4)]) {
+                    Ok(text) => text,
+                    Err(err) => std::str::from_utf8(&rest[..err.valid_up_to()]).unwrap()
+                };
+                match valid.chars().next() {
+                    Some(c) => (Key::Char(c), c.len_utf8()),
+                    // invalid or cut off characters are skipped byte by byte
+                    None => (Key::Char('\u{FFFD}'), 1)
+                }
+            }
+        };
+        keys.push_back(key);
+        i += length;
+    }
+}
+
+/// Columns and rows of the terminal, if stdout is one.
+#[cfg(unix)]
+fn terminal_size() -> Option<(usize, usize)> {
+    let mut size = std::mem::MaybeUninit::<libc::winsize>::uninit();
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) } {
+        0 => {
+            let size = unsafe { size.assume_init() };
+            Some((size.ws_col.max(1) as usize, size.ws_row.max(1) as usize))
+        },
+        _ => None
+    }
+}
+
+#[cfg(not(unix))]
+fn terminal_size() -> Option<(usize, usize)> {
+    None
+}
+
+/// Number of slices that fit the terminal, if its size is known. Each label
+/// of the legend takes two rows, the path above the chart and the help below
+/// it take another seven. The pie grows with the legend, so it's kept narrow
+/// enough to leave `LEGEND_WIDTH` columns for the labels.
+pub fn automatic_slices() -> Option<usize> {
+    let (columns, rows) = terminal_size()?;
+    // the remaining entries and the deleted ones when comparing scans have labels as well
+    let mut slices = (rows.saturating_sub(7) / 2).saturating_sub(2).max(1);
+    while slices > 1 && chart_width(slices + 2) + LEGEND_WIDTH > columns {
+        slices -= 1;
+    }
+    Some(slices)
+}
+
+/// The terminal while the interface is shown. Keys are read one by one
+/// without being echoed, the previous state is restored when it's dropped.
+pub struct Terminal {
+    #[cfg(unix)]
+    original: Option<libc::termios>,
+    keys: VecDeque<Key>,
+}
+
+impl Terminal {
+    #[cfg(unix)]
+    pub fn enter() -> io::Result<Terminal> {
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // stdin might not be a terminal, keys are read anyway then
+        let original = match unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } {
+            0 => Some(unsafe { termios.assume_init() }),
+            _ => None
+        };
+        if let Some(mut raw) = original {
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) };
+        }
+        // alternate screen, hidden cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Terminal { original, keys: VecDeque::new() })
+    }
+
+    /// Without raw mode, keys are only read after a line was entered.
+    #[cfg(not(unix))]
+    pub fn enter() -> io::Result<Terminal> {
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Terminal { keys: VecDeque::new() })
+    }
+
+    /// Columns and rows of the terminal.
+    pub fn size() -> (usize, usize) {
+        terminal_size().unwrap_or((80, 24))
+    }
+
+    /// Replaces the screen with `body`, followed by `prompt` with the cursor
+    /// after it, or by the dimmed `help` line cut to the width of the terminal.
+    pub fn draw(body: &str, prompt: Option<&str>, help: &str) -> io::Result<()> {
+        let mut screen = format!("\x1b[H\x1b[2J{}", body);
+        match prompt {
+            Some(prompt) => screen.push_str(&format!("{}\x1b[?25h", prompt)),
+            None => {
+                let help: String = help.chars().take(Terminal::size().0).collect();
+                screen.push_str(&format!("\x1b[2m{}\x1b[22m\x1b[?25l", help));
+            }
+        }
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()
+    }
+
+    /// Reads a line of input after `prompt`, starting with `line`. `draw`
+    /// draws the screen with the given prompt. Returns `None` if it was
+    /// cancelled with escape.
+    pub fn read_line(&mut self, draw: impl Fn(&str) -> io::Result<()>, prompt: &str, mut line: String)
+            -> io::Result<Option<String>> {
+        loop {
+            draw(&format!("{} {}", prompt, line))?;
+            match self.read_key(POLL)? {
+                Some(Key::Char(c)) => line.push(c),
+                Some(Key::Backspace) => {
+                    line.pop();
+                },
+                Some(Key::Enter) => return Ok(Some(line)),
+                Some(Key::Escape | Key::Interrupt) => return Ok(None),
+                _ => ()
+            }
+        }
+    }
+
+    /// Asks for the confirmation of a deletion, see `read_line` for `draw`.
+    /// A single key answers "y/N" questions, "yes" has to be typed and entered.
+    pub fn confirm(&mut self, draw: impl Fn(&str) -> io::Result<()>, confirmation: &Confirmation)
+            -> io::Result<bool> {
+        if confirmation.type_yes {
+            let answer = self.read_line(draw, &confirmation.question, String::new())?;
+            return Ok(answer.is_some_and(|answer| confirmation.is_confirmed(&answer)))
+        }
+        loop {
+            draw(&format!("{} ", confirmation.question))?;
+            match self.read_key(POLL)? {
+                Some(Key::Char(c)) => return Ok(confirmation.is_confirmed(&c.to_string())),
+                Some(_) => return Ok(false),
+                None => ()
+            }
+        }
+    }
+
+    /// Waits for the next key, at most for `timeout`. Returns `None` without
+    /// a key, and `Key::Escape` if there is nothing left to read.
+    #[cfg(unix)]
+    pub fn read_key(&mut self, timeout: time::Duration) -> io::Result<Option<Key>> {
+        if let Some(key) = self.keys.pop_front() {
+            return Ok(Some(key))
+        }
+        let mut stdin = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut stdin, 1, timeout.as_millis() as libc::c_int) } <= 0 {
+            return Ok(None)
+        }
+        let mut buffer = [0u8; 64];
+        let read = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+        match read {
+            read if read < 0 => Err(io::Error::last_os_error()),
+            0 => Ok(Some(Key::Escape)),
+            read => {
+                parse_keys(&buffer[..read as usize], &mut self.keys);
+                Ok(self.keys.pop_front())
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn read_key(&mut self, _timeout: time::Duration) -> io::Result<Option<Key>> {
+        if self.keys.is_empty() {
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                return Ok(Some(Key::Escape))
+            }
+            parse_keys(line.as_bytes(), &mut self.keys);
+        }
+        Ok(self.keys.pop_front())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().ok();
+        #[cfg(unix)]
+        if let Some(original) = &self.original {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+        }
+    }
+}
+
+/// The state of the interface.
+struct Session<'a> {
+    tree: &'a mut Tree,
+    stack: Vec<View>,
+    history: &'a mut Vec<(usize, Removal)>,
+    settings: &'a Settings,
+    /// Shown below the chart until the next key.
+    message: Vec<String>,
+}
+
+impl Session<'_> {
+    fn view(&self) -> &View {
+        self.stack.last().unwrap()
+    }
+
+    /// Whether the entry at a position of the current page can be selected.
+    fn is_selectable(&self, position: usize) -> bool {
+        let view = self.view();
+        position < self.settings.slices() && view.entries.get(view.skip + position)
+            .is_some_and(|&e| !self.tree.entries[e].deleted)
+    }
+
+    /// The selected entry, if there is one on the page.
+    fn selected(&self) -> Option<usize> {
+        let view = self.view();
+        view.entries.get(view.skip + view.selected).copied().filter(|_| self.is_selectable(view.selected))
+    }
+
+    /// Moves the selection by `step` entries of the page, skipping deleted ones.
+    fn select(&mut self, step: isize) {
+        let mut position = self.view().selected as isize + step;
+        while position >= 0 && position < self.settings.slices() as isize {
+            if self.is_selectable(position as usize) {
+                self.stack.last_mut().unwrap().selected = position as usize;
+                return
+            }
+            position += step.signum();
+        }
+    }
+
+    /// Sorts the entries of the page again after sizes of hard links moved to
+    /// another link, the selected entry stays selected.
+    fn sort(&mut self) {
+        let selected = self.selected();
+        self.stack.last_mut().unwrap().sort(self.tree, self.settings);
+        if let Some(position) = self.view().entries.iter().position(|&e| Some(e) == selected) {
+            self.show(position);
+        }
+        self.fix_selection();
+    }
+
+    /// Selects the first entry of the page if the selected one is gone.
+    fn fix_selection(&mut self) {
+        if self.selected().is_none() {
+            self.stack.last_mut().unwrap().selected = 0;
+            if !self.is_selectable(0) {
+                self.select(1);
+            }
+        }
+    }
+
+    /// Draws the whole screen. `prompt` replaces the help line, e.g. for a
+    /// confirmation.
+    fn draw(&self, prompt: Option<&str>) -> io::Result<()> {
+        let (columns, _) = Terminal::size();
+        let view = self.view();
+        let dir = &self.tree.entries[view.dir];
+        let mut screen = String::new();
+        let slices = self.settings.slices();
+        let pages = view.entries.len().div_ceil(slices).max(1);
+        screen.push_str(&format!("{} ({}), page {} of {}\n\n", dir.path.display(),
+            to_readable_size(dir.size), view.skip / slices + 1, pages));
+
+        let mut data = create_current_data(self.tree, view, self.settings, columns);
+        // deleted entries before the selected one don't have a slice
+        let slice = (0..view.selected).filter(|&p| self.is_selectable(p)).count();
+        if let Some(selected) = data.get_mut(slice).filter(|_| self.selected().is_some()) {
+            selected.label = format!("\x1b[7m{}\x1b[27m", selected.label);
+            selected.fill = '█';
+        }
+        let mut chart = Vec::new();
+        draw_chart(&mut chart, &data)?;
+        screen.push_str(&String::from_utf8_lossy(&chart));
+        screen.push('\n');
+
+        for line in &self.message {
+            screen.push_str(&format!("{}\n", line));
+        }
+        let help = match self.settings.depth {
+            Some(_) => "↑↓ select  d delete  1-9 delete several  → zoom in  ← back  u undo  \
+                n/p next/previous page  g go to page  / search  i info  q quit",
+            None => "↑↓ select  d delete  1-9 delete several  u undo  \
+                n/p next/previous page  g go to page  / search  i info  q quit"
+        };
+        Terminal::draw(&screen, prompt, help)
+    }
+
+    /// Reads a line of input below the chart, see `Terminal::read_line`.
+    fn read_line(&self, terminal: &mut Terminal, prompt: &str, line: String) -> io::Result<Option<String>> {
+        terminal.read_line(|prompt| self.draw(Some(prompt)), prompt, line)
+    }
+
+    /// Asks for the confirmation of a deletion below the chart, with its
+    /// notes above the question.
+    fn confirm(&mut self, terminal: &mut Terminal, confirmation: &Confirmation) -> io::Result<bool> {
+        self.message = confirmation.notes.clone();
+        terminal.confirm(|prompt| self.draw(Some(prompt)), confirmation)
+    }
+
+    /// Parses a choice of entries on the current page like "1 3 5", "1-4" or
+    /// "all". Deleted entries are left out of ranges.
+    fn parse_choice(&self, input: &str) -> Result<Vec<usize>, String> {
+        let view = self.view();
+        let slices = self.settings.slices();
+        let mut positions = Vec::<usize>::new();
+        for part in input.split(|c: char| c.is_whitespace() || c == ',').filter(|p| !p.is_empty()) {
+            let invalid = || format!("{} is not a valid choice, e.g. 1 3 5, 1-4 or all", part);
+            let number = |n: &str| n.trim().parse::<usize>().map_err(|_| invalid());
+            let (first, last) = match part.split_once('-') {
+                _ if part.eq_ignore_ascii_case("all") => (1, slices),
+                Some((first, last)) => (number(first)?, number(last)?),
+                None => {
+                    let n = number(part)?;
+                    if !self.is_selectable(n.wrapping_sub(1)) {
+                        return Err(format!("There is no entry {} on this page", n))
+                    }
+                    (n, n)
+                }
+            };
+            if first == 0 || first > last {
+                return Err(invalid())
+            }
+            for position in first - 1..last.min(slices) {
+                if self.is_selectable(position) && !positions.contains(&position) {
+                    positions.push(position);
+                }
+            }
+        }
+        if positions.is_empty() {
+            return Err(String::from("No entries chosen"))
+        }
+        Ok(positions.iter().map(|p| view.entries[view.skip + p]).collect())
+    }
+
+    /// Deletes entries after asking for confirmation once. Entries that
+    /// can't be deleted are reported, the others are deleted anyway.
+    fn delete(&mut self, terminal: &mut Terminal, entries: &[usize]) -> io::Result<()> {
+        let files: Vec<_> = entries.iter().map(|&e| &self.tree.entries[e]).collect();
+        let confirmation = Confirmation::for_entries(&files, self.settings);
+        if !self.confirm(terminal, &confirmation)? {
+            self.message = Vec::new();
+            return Ok(())
+        }
+        let mut removed = Vec::new();
+        let mut failed = Vec::new();
+        for &index in entries {
+            match remove_confirmed(&self.tree.entries[index], self.settings) {
+                Ok((removal, message)) => {
+                    self.tree.remove(index);
+                    // permanent deletions can't be undone
+                    if !matches!(removal, Removal::Deleted) {
+                        self.history.push((index, removal));
+                    }
+                    removed.push((index, message));
+                },
+                Err(err) => failed.push(format!("Couldn't delete {}: {}",
+                    self.tree.entries[index].path.display(), err))
+            }
+        }
+        self.message = match removed.as_slice() {
+            [(_, message)] => vec![message.clone()],
+            [] => Vec::new(),
+            _ => {
+                let size: u64 = removed.iter().map(|&(i, _)| self.tree.entries[i].size).sum();
+                let done = match (self.settings.dry_run, self.settings.trash) {
+                    (true, _) => "marked for deletion (dry run)",
+                    (_, true) => "moved to trash",
+                    _ => "deleted"
+                };
+                vec![format!("{} entries with {} {}", removed.len(), to_readable_size(size), done)]
+            }
+        };
+        self.message.extend(failed);
+        self.sort();
+        Ok(())
+    }
+
+    /// Asks which entries of the page to delete, starting with `input`.
+    fn delete_chosen(&mut self, terminal: &mut Terminal, input: String) -> io::Result<()> {
+        let input = match self.read_line(terminal, "Delete entries (e.g. 1 3 5, 1-4 or all):", input)? {
+            Some(input) => input,
+            None => return Ok(())
+        };
+        match self.parse_choice(&input) {
+            Ok(entries) => self.delete(terminal, &entries),
+            Err(err) => {
+                self.message = vec![err];
+                Ok(())
+            }
+        }
+    }
+
+    /// Asks for an entry of the page, the selected one by default, and shows
+    /// all of its metadata.
+    fn show_info(&mut self, terminal: &mut Terminal) -> io::Result<()> {
+        let selected = self.selected().map(|_| (self.view().selected + 1).to_string());
+        let input = match self.read_line(terminal, "Show info of entry:", selected.unwrap_or_default())? {
+            Some(input) => input,
+            None => return Ok(())
+        };
+        let position = input.trim().parse::<usize>().ok()
+            .and_then(|n| n.checked_sub(1))
+            .filter(|&p| self.is_selectable(p));
+        self.message = match position {
+            Some(position) => {
+                let path = &self.tree.entries[self.view().entries[self.view().skip + position]].path;
+                info::describe(path).unwrap_or_else(|err| vec![format!("Couldn't read {}: {}", path.display(), err)])
+            },
+            None => vec![format!("There is no entry {} on this page", input.trim())]
+        };
+        Ok(())
+    }
+
+    /// Shows the page with the entry at `position` of the listing and selects it.
+    fn show(&mut self, position: usize) {
+        let slices = self.settings.slices();
+        let view = self.stack.last_mut().unwrap();
+        view.skip = position / slices * slices;
+        view.selected = position % slices;
+        self.fix_selection();
+    }
+
+    fn next_page(&mut self) {
+        let view = self.view();
+        if view.skip + self.settings.slices() >= view.entries.len() {
+            self.message = vec![String::from("No entries left in this directory")];
+            return
+        }
+        self.show(view.skip + self.settings.slices());
+    }
+
+    fn previous_page(&mut self) {
+        let view = self.view();
+        if view.skip == 0 {
+            self.message = vec![String::from("Already on the first page")];
+            return
+        }
+        self.show(view.skip - self.settings.slices());
+    }
+
+    /// Asks for the number of a page and shows it.
+    fn go_to_page(&mut self, terminal: &mut Terminal) -> io::Result<()> {
+        let pages = self.view().entries.len().div_ceil(self.settings.slices()).max(1);
+        let input = match self.read_line(terminal, &format!("Go to page (1-{}):", pages), String::new())? {
+            Some(input) => input,
+            None => return Ok(())
+        };
+        match input.trim().parse::<usize>() {
+            Ok(page @ 1..) if page <= pages => self.show((page - 1) * self.settings.slices()),
+            _ => self.message = vec![format!("{} is not a page between 1 and {}", input.trim(), pages)]
+        }
+        Ok(())
+    }
+
+    /// Asks for a search term and shows the first entry whose path contains
+    /// it, ignoring case.
+    fn search(&mut self, terminal: &mut Terminal) -> io::Result<()> {
+        let term = match self.read_line(terminal, "Search:", String::new())? {
+            Some(term) if !term.is_empty() => term.to_lowercase(),
+            _ => return Ok(())
+        };
+        let found = self.view().entries.iter().position(|&e| {
+            let entry = &self.tree.entries[e];
+            !entry.deleted && entry.path.to_string_lossy().to_lowercase().contains(&term)
+        });
+        match found {
+            Some(position) => self.show(position),
+            None => self.message = vec![format!("Nothing matches {}", term)]
+        }
+        Ok(())
+    }
+
+    fn zoom_in(&mut self) {
+        match self.selected() {
+            Some(index) if self.tree.entries[index].is_dir && self.settings.depth.is_some() =>
+                self.stack.push(View::new(self.tree, index, self.settings)),
+            Some(_) if self.settings.depth.is_some() => self.message = vec![String::from("Not a directory")],
+            _ => ()
+        }
+    }
+
+    fn back_up(&mut self) {
+        if self.stack.len() == 1 {
+            self.message = vec![String::from("Already at the top directory")];
+            return
+        }
+        self.stack.pop();
+        // sizes might have changed in the meantime
+        let slices = self.settings.slices();
+        let view = self.stack.last_mut().unwrap();
+        let selected = view.entries.get(view.skip + view.selected).copied();
+        view.entries = View::list(self.tree, view.dir, self.settings);
+        match view.entries.iter().position(|&e| Some(e) == selected) {
+            Some(position) => {
+                view.skip = position / slices * slices;
+                view.selected = position % slices;
+            },
+            None => {
+                view.skip = view.skip.min(view.entries.len().saturating_sub(1))
+                    / slices * slices;
+            }
+        }
+        self.fix_selection();
+    }
+}
+
+/// Runs the interface until the user quits or there is nothing left to show.
+/// Returns a message to show afterwards.
+pub fn run(tree: &mut Tree, history: &mut Vec<(usize, Removal)>, settings: &Settings)
+        -> Result<Option<String>, Box<dyn Error>> {
+    let stack = vec![View::new(tree, 0, settings)];
+    let mut session = Session { tree, stack, history, settings, message: Vec::new() };
+    session.fix_selection();
+    let mut terminal = Terminal::enter()?;
+    let mut size = None;
+
+    loop {
+        // the page only changes after a key or a resize
+        if size != Some(Terminal::size()) {
+            if create_current_data(session.tree, session.view(), settings, Terminal::size().0).is_empty() {
+                if session.stack.len() == 1 {
+                    let farewell = match settings.diff {
+                        Some(_) => "Nothing grew since the earlier scan, quitting.",
+                        None => "No files left, quitting."
+                    };
+                    return Ok(Some(String::from(farewell)))
+                }
+                session.back_up();
+                session.message = match settings.diff {
+                    Some(_) => vec![String::from("Nothing grew in this directory, went back up.")],
+                    None => vec![String::from("Directory is empty, went back up.")]
+                };
+                continue
+            }
+            size = Some(Terminal::size());
+            // the number of slices follows the height, keep the selected entry on the page
+            let view = session.view();
+            session.show(view.skip + view.selected);
+            session.draw(None)?;
+        }
+
+        let key = match terminal.read_key(POLL)? {
+            Some(key) => key,
+            None => continue
+        };
+        session.message = Vec::new();
+        match key {
+            Key::Up | Key::Char('k') => session.select(-1),
+            Key::Down | Key::Char('j') => session.select(1),
+            Key::Char(c @ '1'..='9') => session.delete_chosen(&mut terminal, c.to_string())?,
+            Key::Char(':') => session.delete_chosen(&mut terminal, String::new())?,
+            Key::Right | Key::Enter | Key::Char('z') => session.zoom_in(),
+            Key::Left | Key::Backspace | Key::Char('b') => session.back_up(),
+            Key::Delete | Key::Char('d') => {
+                if let Some(selected) = session.selected() {
+                    session.delete(&mut terminal, &[selected])?;
+                }
+            },
+            Key::PageDown | Key::Char('n') => session.next_page(),
+            Key::PageUp | Key::Char('p') => session.previous_page(),
+            Key::Char('g') => session.go_to_page(&mut terminal)?,
+            Key::Char('/') => session.search(&mut terminal)?,
+            Key::Char('i') => session.show_info(&mut terminal)?,
+            Key::Char('u') => {
+                session.message = vec![undo_deletion(session.tree, &mut session.stack, session.history,
+                    settings)];
+                session.fix_selection();
+            },
+            Key::Char('q') | Key::Escape | Key::Interrupt => return Ok(None),
+            _ => ()
+        }
+        size = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        let mut keys = VecDeque::new();
+        parse_keys(bytes, &mut keys);
+        keys.into_iter().collect()
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(keys(b"\x1b[A\x1bOB\x1b[C\x1b[D"), [Key::Up, Key::Down, Key::Right, Key::Left]);
+        assert_eq!(keys(b"\x1b[3~\x1b[5~\x1b[6~"), [Key::Delete, Key::PageUp, Key::PageDown]);
+        assert_eq!(keys(b"\x1b"), [Key::Escape]);
+        assert_eq!(keys(b"\x1bq"), [Key::Escape, Key::Char('q')]);
+    }
+
+    #[test]
+    fn control_keys() {
+        assert_eq!(keys(b"\r\n\x7f\x08\x03"),
+            [Key::Enter, Key::Enter, Key::Backspace, Key::Backspace, Key::Interrupt]);
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(keys(b"d12"), [Key::Char('d'), Key::Char('1'), Key::Char('2')]);
+        assert_eq!(keys("äb€".as_bytes()), [Key::Char('ä'), Key::Char('b'), Key::Char('€')]);
+        assert_eq!(keys(b"\xffa"), [Key::Char('\u{FFFD}'), Key::Char('a')]);
+        assert_eq!(keys(b""), []);
+    }
+
+    #[test]
+    fn choices() {
+        let mut tree = Tree::new();
+        let root = tree.push(None, "root", 0, true);
+        let files: Vec<usize> = (1..=5).map(|i| tree.push(Some(root), &format!("f{}", i), 100 - i, false)).collect();
+        let settings = Settings { depth: None, trash: false, dry_run: true, diff: None, slices: Some(3),
+            relative_paths: false };
+        let mut history = Vec::new();
+        let stack = vec![View::new(&tree, root, &settings)];
+        let session = Session { tree: &mut tree, stack, history: &mut history, settings: &settings,
+            message: Vec::new() };
+
+        assert_eq!(session.parse_choice("1 3"), Ok(vec![files[0], files[2]]));
+        assert_eq!(session.parse_choice("2-3,1 2"), Ok(vec![files[1], files[2], files[0]]));
+        assert_eq!(session.parse_choice("ALL"), Ok(files[..3].to_vec()));
+        // ranges end with the page
+        assert_eq!(session.parse_choice("2-999999999999999"), Ok(vec![files[1], files[2]]));
+        for invalid in ["", "4", "0", "0-2", "3-1", "x", "1-", "-2"] {
+            assert!(session.parse_choice(invalid).is_err(), "{:?} was accepted", invalid);
+        }
+
+        session.tree.remove(files[1]);
+        assert_eq!(session.parse_choice("1-3"), Ok(vec![files[0], files[2]]));
+        assert!(session.parse_choice("2").is_err());
+    }
+}
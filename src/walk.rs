@@ -0,0 +1,397 @@
+//! Walks a directory tree with several threads. Every thread has its own
+//! queue of directories to read and steals from the others once it runs out.
+//! At the end the entries are put into the same order a sequential walk
+//! would have found them in. Directories that didn't change since the last
+//! scan are taken from the cache instead of being read.
+
+use std::{fs, io, mem, path, thread, time, ffi::OsString, collections::VecDeque};
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cache::{self, Cache};
+
+/// The metadata of an entry that is needed for the scan, which can also be
+/// kept in the cache.
+#[derive(Clone)]
+pub struct Stat {
+    pub size: u64,
+    /// Bytes allocated on disk, which differs from `size` for sparse files or
+    /// compressed filesystems.
+    pub allocated: u64,
+    /// Device and inode number, which are the same for all hard links of a file.
+    pub inode: Option<(u64, u64)>,
+    /// Number of hard links.
+    pub links: u64,
+    pub is_dir: bool,
+    pub is_file: bool,
+    /// Set for symlinks, even if the rest is about their target.
+    pub is_symlink: bool,
+    pub modified: Option<time::SystemTime>,
+    pub created: Option<time::SystemTime>,
+    pub accessed: Option<time::SystemTime>,
+}
+
+impl From<&fs::Metadata> for Stat {
+    fn from(metadata: &fs::Metadata) -> Stat {
+        Stat {
+            size: metadata.len(),
+            allocated: allocated(metadata),
+            inode: inode(metadata),
+            links: links(metadata),
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            is_symlink: metadata.file_type().is_symlink(),
+            modified: metadata.modified().ok(),
+            created: metadata.created().ok(),
+            accessed: metadata.accessed().ok()
+        }
+    }
+}
+
+#[cfg(unix)]
+fn allocated(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+/// Without block counts, the size is the best guess.
+#[cfg(not(unix))]
+fn allocated(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn links(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn links(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// Reads the metadata of the target instead if `follow` is set and the entry
+/// is a symlink, unless the target doesn't exist.
+fn resolve(path: &path::Path, metadata: fs::Metadata, follow: bool) -> Stat {
+    match (follow && metadata.file_type().is_symlink()).then(|| fs::metadata(path)) {
+        Some(Ok(target)) => Stat { is_symlink: true, ..Stat::from(&target) },
+        _ => Stat::from(&metadata)
+    }
+}
+
+/// What to do with an entry found while walking.
+pub enum Visit<T, S> {
+    /// Leave the entry out, including the content of directories.
+    Skip,
+    /// Keep the entry.
+    File(T),
+    /// Keep the directory and walk its content with the given state.
+    Dir(T, S),
+}
+
+/// An entry found in a directory. Directories refer to the listing of their content.
+enum Found<T> {
+    Entry(T, Option<usize>),
+    Error(String),
+}
+
+/// A directory waiting to be read.
+struct Job<S> {
+    path: path::PathBuf,
+    depth: usize,
+    modified: Option<time::SystemTime>,
+    state: S,
+    listing: usize,
+    /// Devices and inodes of the directory and its parents, to detect loops
+    /// when following symlinks.
+    ancestors: Vec<(u64, u64)>,
+}
+
+/// Entries and errors with their depth, parents before their content.
+pub type Walked<T> = Vec<(usize, Result<T, String>)>;
+
+/// What a thread found in a directory, and the listing for the new cache.
+type Done<T> = (usize, Vec<Found<T>>, Option<(path::PathBuf, cache::Dir)>);
+
+struct Shared<'a, S> {
+    root: &'a path::Path,
+    follow: bool,
+    /// Cache of the last scan, if it's used.
+    previous: Option<&'a Cache>,
+    queues: Vec<Mutex<VecDeque<Job<S>>>>,
+    /// Directories queued or being read, the walk is done when there are none left.
+    pending: AtomicUsize,
+    next_listing: AtomicUsize,
+    /// Threads without anything to read wait for `wake` while holding this.
+    idle: Mutex<()>,
+    /// Notified when a directory was queued or the walk is done.
+    wake: Condvar,
+}
+
+impl<S> Shared<'_, S> {
+    /// Wakes waiting threads. Taking the lock makes sure a thread that just
+    /// found all queues empty is already waiting.
+    fn notify(&self, all: bool) {
+        let _idle = self.idle.lock().unwrap();
+        if all { self.wake.notify_all() } else { self.wake.notify_one() }
+    }
+}
+
+fn describe_error(path: &path::Path, error: io::Error) -> String {
+    format!("IO error for operation on {}: {}", path.display(), error)
+}
+
+/// Walks `root` with the given number of threads. `visit` is called for every
+/// entry with its depth, metadata and the state of the directory it is in.
+/// Symlinks are followed if `follow` is set, links back to a parent directory
+/// are reported as errors. Returns the kept entries and errors, and the cache
+/// for the next scan.
+pub fn walk<T, S, F>(root: &path::Path, threads: usize, follow: bool, previous: Option<&Cache>,
+        state: S, visit: F) -> (Walked<T>, Cache)
+        where T: Send, S: Send, F: Fn(&path::Path, usize, &Stat, &S) -> io::Result<Visit<T, S>> + Sync {
+    let stat = match fs::metadata(root) {
+        Ok(metadata) => Stat::from(&metadata),
+        Err(error) => return (vec![(0, Err(describe_error(root, error)))], Cache::new())
+    };
+    let (value, state) = match visit(root, 0, &stat, &state) {
+        Ok(Visit::Skip) => return (Vec::new(), Cache::new()),
+        Ok(Visit::File(value)) => return (vec![(0, Ok(value))], Cache::new()),
+        Ok(Visit::Dir(value, state)) => (value, state),
+        Err(error) => return (vec![(0, Err(describe_error(root, error)))], Cache::new())
+    };
+
+    let shared = Shared {
+        root,
+        follow,
+        previous,
+        queues: (0..threads.max(1)).map(|_| Mutex::new(VecDeque::new())).collect(),
+        pending: AtomicUsize::new(1),
+        next_listing: AtomicUsize::new(1),
+        idle: Mutex::new(()),
+        wake: Condvar::new()
+    };
+    shared.queues[0].lock().unwrap().push_back(Job {
+        path: root.to_path_buf(),
+        depth: 0,
+        modified: stat.modified,
+        state,
+        listing: 0,
+        ancestors: stat.inode.filter(|_| follow).into_iter().collect()
+    });
+
+    let done: Vec<Vec<Done<T>>> = if shared.queues.len() == 1 {
+        vec![work(&shared, 0, &visit)]
+    } else {
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..shared.queues.len())
+                .map(|index| {
+                    let (shared, visit) = (&shared, &visit);
+                    scope.spawn(move || work(shared, index, visit))
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        })
+    };
+    let mut listings: Vec<Vec<Found<T>>> = (0..shared.next_listing.into_inner()).map(|_| Vec::new()).collect();
+    let mut cache = Cache::new();
+    for (listing, found, dir) in done.into_iter().flatten() {
+        listings[listing] = found;
+        if let Some((relative, dir)) = dir {
+            cache.insert(relative, dir);
+        }
+    }
+
+    // put the listings back together depth first
+    let mut walked = vec![(0, Ok(value))];
+    let mut stack = vec![mem::take(&mut listings[0]).into_iter()];
+    while let Some(listing) = stack.last_mut() {
+        match listing.next() {
+            Some(Found::Entry(value, content)) => {
+                walked.push((stack.len(), Ok(value)));
+                if let Some(content) = content {
+                    stack.push(mem::take(&mut listings[content]).into_iter());
+                }
+            },
+            Some(Found::Error(error)) => walked.push((stack.len(), Err(error))),
+            None => {
+                stack.pop();
+            }
+        }
+    }
+    (walked, cache)
+}
+
+/// Reads directories until there are none left, first from the own queue,
+/// then from the queues of the other threads. Waits while other threads are
+/// still reading directories that might contain more.
+fn work<T, S, F>(shared: &Shared<S>, index: usize, visit: &F) -> Vec<Done<T>>
+        where F: Fn(&path::Path, usize, &Stat, &S) -> io::Result<Visit<T, S>> {
+    let mut done = Vec::new();
+    loop {
+        let own = shared.queues[index].lock().unwrap().pop_back();
+        match own.or_else(|| steal(shared, index)) {
+            Some(job) => {
+                let listing = job.listing;
+                let (found, dir) = read_dir(shared, index, job, visit);
+                done.push((listing, found, dir));
+                if shared.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    shared.notify(true);
+                }
+            },
+            None if shared.pending.load(Ordering::SeqCst) == 0 => return done,
+            None => {
+                let idle = shared.idle.lock().unwrap();
+                // checked again while holding the lock, so no notification is missed
+                if shared.pending.load(Ordering::SeqCst) > 0
+                        && shared.queues.iter().all(|q| q.lock().unwrap().is_empty()) {
+                    drop(shared.wake.wait(idle).unwrap());
+                }
+            }
+        }
+    }
+}
+
+/// Takes the oldest directory from another queue, which likely has the most
+/// content left.
+fn steal<S>(shared: &Shared<S>, index: usize) -> Option<Job<S>> {
+    let count = shared.queues.len();
+    (1..count).find_map(|offset| shared.queues[(index + offset) % count].lock().unwrap().pop_front())
+}
+
+/// Lists a directory, from the cache if it didn't change since the last scan.
+/// Subdirectories are always checked for changes. Errors come with the path
+/// they occurred at.
+fn list<S>(shared: &Shared<S>, job: &Job<S>, relative: &path::Path)
+        -> Vec<Result<(OsString, Stat), (path::PathBuf, io::Error)>> {
+    if let Some(dir) = shared.previous.and_then(|cache| cache.get(relative, job.modified)) {
+        return dir.entries.iter().map(|(name, stat)| match stat.is_dir || stat.is_symlink {
+            true => fs::symlink_metadata(job.path.join(name))
+                .map(|metadata| (name.clone(), resolve(&job.path.join(name), metadata, shared.follow)))
+                .map_err(|error| (job.path.join(name), error)),
+            false => Ok((name.clone(), stat.clone()))
+        }).collect()
+    }
+    let entries = match fs::read_dir(&job.path) {
+        Ok(entries) => entries,
+        Err(error) => return vec![Err((job.path.clone(), error))]
+    };
+    entries.map(|entry| match entry {
+        Ok(entry) => entry.metadata()
+            .map(|metadata| (entry.file_name(), resolve(&entry.path(), metadata, shared.follow)))
+            .map_err(|error| (entry.path(), error)),
+        Err(error) => Err((job.path.clone(), error))
+    }).collect()
+}
+
+/// Reads a directory and queues its subdirectories. Returns the listing for
+/// the new cache as well, unless there were errors.
+fn read_dir<T, S, F>(shared: &Shared<S>, index: usize, job: Job<S>, visit: &F)
+        -> (Vec<Found<T>>, Option<(path::PathBuf, cache::Dir)>)
+        where F: Fn(&path::Path, usize, &Stat, &S) -> io::Result<Visit<T, S>> {
+    let relative = job.path.strip_prefix(shared.root).unwrap_or(&job.path).to_path_buf();
+    let entries = list(shared, &job, &relative);
+    let complete = entries.iter().all(|e| e.is_ok());
+    let mut found = Vec::new();
+    let mut cached = Vec::new();
+    for entry in entries {
+        let (name, stat) = match entry {
+            Ok(entry) => entry,
+            Err((path, error)) => {
+                found.push(Found::Error(describe_error(&path, error)));
+                continue
+            }
+        };
+        let path = job.path.join(&name);
+        if stat.is_dir && stat.inode.is_some_and(|inode| job.ancestors.contains(&inode)) {
+            found.push(Found::Error(format!("File system loop found: {} points to one of its \
+                parent directories", path.display())));
+            cached.push((name, stat));
+            continue
+        }
+        match visit(&path, job.depth + 1, &stat, &job.state) {
+            Ok(Visit::Skip) => {},
+            Ok(Visit::File(value)) => found.push(Found::Entry(value, None)),
+            Ok(Visit::Dir(value, state)) => {
+                let listing = shared.next_listing.fetch_add(1, Ordering::SeqCst);
+                let mut ancestors = Vec::new();
+                if let Some(inode) = stat.inode.filter(|_| shared.follow) {
+                    ancestors.extend_from_slice(&job.ancestors);
+                    ancestors.push(inode);
+                }
+                shared.pending.fetch_add(1, Ordering::SeqCst);
+                shared.queues[index].lock().unwrap().push_back(Job {
+                    path,
+                    depth: job.depth + 1,
+                    modified: stat.modified,
+                    state,
+                    listing,
+                    ancestors
+                });
+                shared.notify(false);
+                found.push(Found::Entry(value, Some(listing)));
+            },
+            Err(error) => found.push(Found::Error(describe_error(&path, error)))
+        }
+        cached.push((name, stat));
+    }
+    let dir = match (complete, job.modified) {
+        (true, Some(modified)) => Some((relative, cache::Dir { modified, entries: cached })),
+        _ => None
+    };
+    (found, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk_paths(root: &path::Path, threads: usize, previous: Option<&Cache>)
+            -> (Walked<path::PathBuf>, Cache) {
+        walk(root, threads, true, previous, (), |path, _, stat, _| {
+            let relative = path.strip_prefix(root).unwrap().to_path_buf();
+            Ok(match (stat.is_dir, path.file_name().is_some_and(|n| n == "skipped")) {
+                (_, true) => Visit::Skip,
+                (true, _) => Visit::Dir(relative, ()),
+                (false, _) => Visit::File(relative)
+            })
+        })
+    }
+
+    #[test]
+    fn same_order_with_threads() {
+        let root = std::env::temp_dir().join(format!("piecut-walk-{}", std::process::id()));
+        for i in 0..12 {
+            let dir = root.join(format!("d{}", i)).join(format!("e{}", i % 3));
+            fs::create_dir_all(dir.join("skipped")).unwrap();
+            for j in 0..i {
+                fs::write(dir.join(format!("f{}", j)), "x").unwrap();
+                fs::write(dir.parent().unwrap().join(format!("g{}", j)), "x").unwrap();
+            }
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("..", root.join("d1").join("loop")).unwrap();
+
+        let (sequential, cache) = walk_paths(&root, 1, None);
+        assert_eq!(sequential.iter().filter(|(_, e)| e.is_ok()).count(), 1 + 12 * 2 + 2 * (0..12).sum::<usize>());
+        assert!(sequential.iter().all(|(_, e)| e.as_ref().map_or(true, |p| !p.ends_with("skipped"))));
+        #[cfg(unix)]
+        assert_eq!(sequential.iter().filter(|(_, e)| e.is_err()).count(), 1);
+        for threads in [2, 8] {
+            assert_eq!(walk_paths(&root, threads, None).0, sequential);
+            assert_eq!(walk_paths(&root, threads, Some(&cache)).0, sequential);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}